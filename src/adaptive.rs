@@ -0,0 +1,109 @@
+use image::{imageops, GrayImage, Luma};
+
+use crate::cli::{Cli, TiledArgs};
+use crate::histogram_processing;
+use crate::image_processing::tile_neighbours;
+use crate::spatial;
+use crate::stats::Histogram;
+use crate::runner::Runner;
+
+// One cell of the tile grid with the overlapping window its thresholds were computed on
+pub struct Tile {
+    pub column: u32,
+    pub row: u32,
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+    pub thresholds: Vec<u8>,
+}
+
+// Thresholds of every tile, row-major, anchored at the centres of the grid cells
+pub struct TileGrid {
+    pub tiles: Vec<Tile>,
+    columns: usize,
+    centres_x: Vec<f64>,
+    centres_y: Vec<f64>,
+}
+
+// Cells split the image evenly, the window of a cell extends it by `overlap` times the cell
+// size on both sides, clamped at the image borders. Returns the window and the cell centre
+fn window(cell: u32, cells: u32, size: u32, overlap: f64) -> (u32, u32, f64) {
+    let (start, end) = (cell * size / cells, (cell + 1) * size / cells);
+    let margin = ((end - start) as f64 * overlap).round() as u32;
+    (start.saturating_sub(margin), (end + margin).min(size), (start + end) as f64 / 2.0)
+}
+
+// Runs the metric/method on the histogram of every window, tiles are named
+// {image}_tile{column}_{row} in the run logs
+pub fn tile_thresholds(file_stem: &str, gray_img: &GrayImage, args: &TiledArgs, runner: Runner, cli: &Cli) -> TileGrid {
+    let (tiles, overlap) = (args.tiles, args.overlap);
+    let (width, height) = gray_img.dimensions();
+    if tiles == 0 || tiles > width.min(height) {
+        panic!("The number of tiles per side must lie in 1..={} for this image.", width.min(height));
+    }
+    if overlap < 0.0 {
+        panic!("The tile overlap must not be negative.");
+    }
+
+    let mut grid = TileGrid {
+        tiles: vec![],
+        columns: tiles as usize,
+        centres_x: vec![],
+        centres_y: vec![],
+    };
+    for row in 0..tiles {
+        let (y0, y1, centre_y) = window(row, tiles, height, overlap);
+        grid.centres_y.push(centre_y);
+        for column in 0..tiles {
+            let (x0, x1, centre_x) = window(column, tiles, width, overlap);
+            if row == 0 {
+                grid.centres_x.push(centre_x);
+            }
+
+            let tile_img = imageops::crop_imm(gray_img, x0, y0, x1 - x0, y1 - y0).to_image();
+            let mut histogram = Histogram::from_image(&tile_img);
+            if spatial::is_2d(&args.metric) {
+                histogram = histogram.with_joint(&tile_img);
+            }
+            if !cli.histogram.is_identity() {
+                histogram = histogram_processing::preprocess(&histogram, &cli.histogram);
+            }
+            let tile_name = format!("{}_tile{}_{}", file_stem, column, row);
+            println!("Tile ({}, {}): {}x{} at ({}, {})", column, row, x1 - x0, y1 - y0, x0, y0);
+            let thresholds = runner.run(&tile_name, &histogram, args.k, &cli.search);
+
+            grid.tiles.push(Tile {
+                column,
+                row,
+                x: x0,
+                y: y0,
+                width: x1 - x0,
+                height: y1 - y0,
+                thresholds,
+            });
+        }
+    }
+    grid
+}
+
+// One map per threshold index, every pixel blends the thresholds of the four surrounding tile
+// centres bilinearly, as CLAHE blends its tile mappings. Blends of sorted threshold vectors stay
+// sorted, so the maps never cross
+pub fn threshold_maps(grid: &TileGrid, width: u32, height: u32) -> Vec<GrayImage> {
+    let thresholds = grid.tiles[0].thresholds.len();
+    let mut maps = vec![GrayImage::new(width, height); thresholds];
+    for y in 0..height {
+        let (top, bottom, wy) = tile_neighbours(y as f64 + 0.5, &grid.centres_y);
+        for x in 0..width {
+            let (left, right, wx) = tile_neighbours(x as f64 + 0.5, &grid.centres_x);
+            let at = |column: usize, row: usize, i: usize| grid.tiles[row * grid.columns + column].thresholds[i] as f64;
+            for (i, map) in maps.iter_mut().enumerate() {
+                let upper = at(left, top, i) * (1.0 - wx) + at(right, top, i) * wx;
+                let lower = at(left, bottom, i) * (1.0 - wx) + at(right, bottom, i) * wx;
+                map.put_pixel(x, y, Luma([(upper * (1.0 - wy) + lower * wy).round() as u8]));
+            }
+        }
+    }
+    maps
+}
@@ -0,0 +1,96 @@
+use clap::ValueEnum;
+
+use crate::stats::{self, Histogram};
+use crate::kapur;
+
+// Model selection criterion used to pick the number of classes
+#[derive(Clone, Copy, Debug, ValueEnum)]
+pub enum Criterion {
+    /// Otsu separability eta = between-class variance / total variance
+    Eta,
+    /// Kapur total entropy of the thresholded histogram
+    EntropyGain,
+    /// Bayesian information criterion of the Gaussian class model
+    Bic,
+}
+
+impl Criterion {
+    pub fn name(self) -> &'static str {
+        match self {
+            Criterion::Eta => "eta",
+            Criterion::EntropyGain => "entropy_gain",
+            Criterion::Bic => "bic",
+        }
+    }
+
+    // Value of the criterion for the thresholds found for one k
+    pub fn score(self, histogram: &Histogram, thresholds: &[usize]) -> f64 {
+        let intensity_levels = histogram.levels();
+        match self {
+            Criterion::Eta => {
+                let total_variance = stats::calculate_total_variance(histogram);
+                if total_variance > 0.0 {
+                    stats::calculate_between_class_variance(histogram, thresholds, intensity_levels) / total_variance
+                } else {
+                    0.0
+                }
+            }
+            Criterion::EntropyGain => kapur::calculate_total_entropy(histogram, thresholds, intensity_levels),
+            Criterion::Bic => calculate_bic(histogram, thresholds),
+        }
+    }
+
+    // Index of the selected k in `scores`, which holds one score per k starting at k = 2.
+    // BIC is minimised. Eta and entropy keep growing with k, so the smallest k whose
+    // step to k + 1 gains less than `tolerance` is taken (absolute gain for eta,
+    // relative gain for entropy)
+    pub fn select(self, scores: &[f64], tolerance: f64) -> usize {
+        match self {
+            Criterion::Bic => {
+                let mut best = 0;
+                for (i, &score) in scores.iter().enumerate() {
+                    if score < scores[best] {
+                        best = i;
+                    }
+                }
+                best
+            }
+            Criterion::Eta | Criterion::EntropyGain => {
+                for i in 0..scores.len().saturating_sub(1) {
+                    let gain = scores[i + 1] - scores[i];
+                    let gain = match self {
+                        Criterion::EntropyGain if scores[i] != 0.0 => gain / scores[i].abs(),
+                        _ => gain,
+                    };
+                    if gain < tolerance {
+                        return i;
+                    }
+                }
+                scores.len() - 1
+            }
+        }
+    }
+}
+
+// BIC of the histogram modelled as one Gaussian per class, with 3k - 1 free
+// parameters (weights, means, variances). Per class the log-likelihood is
+//   N P_c (ln P_c - ln(2 pi sigma_c^2) / 2 - 1/2)
+pub fn calculate_bic(histogram: &Histogram, thresholds: &[usize]) -> f64 {
+    let intensity_levels = histogram.levels();
+    let total_pixels = histogram.total;
+    let classes = stats::calculate_class_statistics(histogram, thresholds, intensity_levels);
+
+    let mut log_likelihood = 0.0;
+    for (i, &p) in classes.prob.iter().enumerate() {
+        if p > 0.0 {
+            // Same variance floor as the minimum error criterion
+            let variance = classes.variance[i].max(1.0 / 12.0);
+            log_likelihood += p * (p.ln() - 0.5 * (2.0 * std::f64::consts::PI * variance).ln() - 0.5);
+        }
+    }
+    log_likelihood *= total_pixels;
+
+    let k = thresholds.len() + 1;
+    let parameters = (3 * k - 1) as f64;
+    -2.0 * log_likelihood + parameters * total_pixels.ln()
+}
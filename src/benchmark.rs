@@ -0,0 +1,105 @@
+use crate::search::Metric;
+use crate::significance;
+
+// Objective statistics of one method over the repeated runs
+pub struct MethodSummary {
+    pub method: String,
+    pub mean: f64,
+    pub std: f64,
+    pub best: f64,
+    pub worst: f64,
+    pub success_rate: f64,
+    pub mean_time_ms: f64,
+}
+
+// Everything reported for one (metric, k) pair of an image
+pub struct Section {
+    pub metric: String,
+    pub k: usize,
+    pub optimum: f64,
+    pub summaries: Vec<MethodSummary>,
+    pub friedman: Option<(f64, f64)>,
+    // Method pair, Wilcoxon statistic and p-value
+    pub wilcoxon: Vec<(String, String, f64, f64)>,
+}
+
+// A run succeeds when it is within `tolerance` of the optimum, relative to the optimum
+// once it is above one in magnitude
+fn is_success(metric: &Metric, value: f64, optimum: f64, tolerance: f64) -> bool {
+    metric.direction.gain(optimum, value) <= tolerance * optimum.abs().max(1.0)
+}
+
+pub fn summarise(
+    method: &str,
+    metric: &Metric,
+    values: &[f64],
+    times_ms: &[f64],
+    optimum: f64,
+    tolerance: f64,
+) -> MethodSummary {
+    let n = values.len() as f64;
+    let mean = values.iter().sum::<f64>() / n;
+    let std = if values.len() > 1 {
+        (values.iter().map(|v| (v - mean).powi(2)).sum::<f64>() / (n - 1.0)).sqrt()
+    } else {
+        0.0
+    };
+    let mut best = metric.direction.worst();
+    let mut worst = values[0];
+    for &value in values {
+        if metric.direction.improves(value, best) {
+            best = value;
+        }
+        if metric.direction.improves(worst, value) {
+            worst = value;
+        }
+    }
+    let successes = values
+        .iter()
+        .filter(|&&value| is_success(metric, value, optimum, tolerance))
+        .count();
+
+    MethodSummary {
+        method: method.to_string(),
+        mean,
+        std,
+        best,
+        worst,
+        success_rate: successes as f64 / n,
+        mean_time_ms: times_ms.iter().sum::<f64>() / times_ms.len() as f64,
+    }
+}
+
+// Friedman over all methods and Wilcoxon between every pair, runs paired by seed
+pub fn compare(
+    metric_name: &str,
+    k: usize,
+    optimum: f64,
+    metric: &Metric,
+    summaries: Vec<MethodSummary>,
+    samples: &[Vec<f64>],
+) -> Section {
+    // Friedman ranks low values first, so maximised objectives are negated
+    let oriented: Vec<Vec<f64>> = samples
+        .iter()
+        .map(|values| values.iter().map(|&v| -metric.direction.gain(v, 0.0)).collect())
+        .collect();
+    let friedman = significance::friedman(&oriented);
+
+    let mut wilcoxon = vec![];
+    for a in 0..samples.len() {
+        for b in a + 1..samples.len() {
+            let (statistic, p_value) = significance::wilcoxon_signed_rank(&samples[a], &samples[b]);
+            wilcoxon.push((summaries[a].method.clone(), summaries[b].method.clone(), statistic, p_value));
+        }
+    }
+
+    Section {
+        metric: metric_name.to_string(),
+        k,
+        optimum,
+        summaries,
+        friedman,
+        wilcoxon,
+    }
+}
@@ -0,0 +1,177 @@
+use std::path::PathBuf;
+
+use clap::{Args, Parser, Subcommand};
+
+use crate::auto_k::Criterion;
+use crate::histogram_processing::{HistogramConfig, Smoothing};
+use crate::image_processing::ImageStep;
+use crate::morphology::SegmentationConfig;
+use crate::search::SearchConfig;
+
+#[derive(Parser)]
+#[command(about = "Multilevel thresholding of grey-level images")]
+pub struct Cli {
+    /// Folder containing the images, a folder picker is shown when omitted
+    #[arg(long, global = true)]
+    pub input: Option<PathBuf>,
+
+    #[command(flatten)]
+    pub search: SearchConfig,
+
+    /// Image preprocessing chain applied in order before the histogram is built, comma separated
+    /// (median:r, gaussian:sigma, stretch[:low:high], clahe:tiles:clip, downscale:factor, crop:x:y:w:h)
+    #[arg(long, global = true, value_delimiter = ',')]
+    pub preprocess: Vec<ImageStep>,
+
+    // Preprocessing of the histogram the searchers run on
+    #[command(flatten)]
+    pub histogram: HistogramConfig,
+
+    // Cleanup of the class map before it is saved
+    #[command(flatten)]
+    pub segmentation: SegmentationConfig,
+
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+#[derive(Subcommand)]
+pub enum Command {
+    /// Run every metric/method pair in the table of process_image (default)
+    Run,
+    /// Optimise Otsu's between-class variance and Kapur's entropy together and save the Pareto front
+    Pareto(ParetoArgs),
+    /// Run one metric/method for k = 2..=max-k and pick k with a model selection criterion
+    AutoK(AutoKArgs),
+    /// Repeat one metric/method with consecutive seeds and plot the convergence of every run
+    Convergence(ConvergenceArgs),
+    /// Repeat seeded runs of several methods and compare them against the exhaustive optimum
+    Benchmark(BenchmarkArgs),
+    /// Threshold the histogram files (.csv or .json, bin index and count) of the input folder instead of its images
+    Histogram(HistogramArgs),
+    /// Threshold every tile of the image separately and blend the thresholds between tile centres
+    Tiled(TiledArgs),
+    /// Threshold the frames of the input folder in order, every run starting from the previous frame's thresholds
+    Sequence(SequenceArgs),
+}
+
+#[derive(Args)]
+pub struct ParetoArgs {
+    /// Number of classes
+    #[arg(short, long, default_value_t = 3)]
+    pub k: usize,
+}
+
+#[derive(Args)]
+pub struct AutoKArgs {
+    /// Metric, as named in the results folder (otsu, kapur, li, ...)
+    #[arg(long, default_value = "otsu")]
+    pub metric: String,
+    /// Search method, as named in the results folder (exhaustive, sa, vns, tabu, hybrid, pso, ga, de_rand, de_best, hs)
+    #[arg(long, default_value = "vns")]
+    pub method: String,
+    /// Largest number of classes tried
+    #[arg(long, default_value_t = 6)]
+    pub max_k: usize,
+    /// Criterion used to pick k
+    #[arg(long, value_enum, default_value_t = Criterion::Eta)]
+    pub criterion: Criterion,
+    /// Smallest gain from k to k + 1 worth another class (absolute for eta, relative for entropy-gain)
+    #[arg(long, default_value_t = 0.01)]
+    pub tolerance: f64,
+}
+
+#[derive(Args)]
+pub struct ConvergenceArgs {
+    /// Metric, as named in the results folder (otsu, kapur, li, ...)
+    #[arg(long, default_value = "otsu")]
+    pub metric: String,
+    /// Search method, any metaheuristic listed for auto-k
+    #[arg(long, default_value = "sa")]
+    pub method: String,
+    /// Number of classes
+    #[arg(short, long, default_value_t = 3)]
+    pub k: usize,
+    /// Number of runs, seeded from --seed upwards
+    #[arg(long, value_parser = clap::value_parser!(u64).range(1..), default_value_t = 10)]
+    pub runs: u64,
+}
+
+#[derive(Args)]
+pub struct BenchmarkArgs {
+    /// Metrics to benchmark, comma separated (otsu, kapur, li, kittler)
+    #[arg(long, value_delimiter = ',', default_value = "otsu,kapur")]
+    pub metrics: Vec<String>,
+    /// Methods to compare, comma separated, as named in the results folder
+    #[arg(long, value_delimiter = ',', default_value = "sa,vns")]
+    pub methods: Vec<String>,
+    /// Numbers of classes, comma separated. The exhaustive reference gets slow from k = 5
+    #[arg(long, value_delimiter = ',', default_value = "2,3,4")]
+    pub ks: Vec<usize>,
+    /// Number of runs per method, seeded from --seed upwards
+    #[arg(long, value_parser = clap::value_parser!(u64).range(1..), default_value_t = 30)]
+    pub runs: u64,
+    /// Relative distance to the exhaustive optimum still counted as a success
+    #[arg(long, default_value_t = 1e-9)]
+    pub success_tolerance: f64,
+}
+
+#[derive(Args)]
+pub struct HistogramArgs {
+    /// Metrics to run, comma separated, as named in the results folder
+    #[arg(long, value_delimiter = ',', default_value = "otsu,kapur")]
+    pub metrics: Vec<String>,
+    /// Methods to run with every metric, comma separated, as named in the results folder
+    #[arg(long, value_delimiter = ',', default_value = "exhaustive")]
+    pub methods: Vec<String>,
+    /// Numbers of classes, comma separated
+    #[arg(long, value_delimiter = ',', default_value = "2,3")]
+    pub ks: Vec<usize>,
+}
+
+#[derive(Args)]
+pub struct TiledArgs {
+    /// Metric, as named in the results folder (otsu, kapur, li, ...)
+    #[arg(long, default_value = "otsu")]
+    pub metric: String,
+    /// Search method, as named in the results folder
+    #[arg(long, default_value = "exhaustive")]
+    pub method: String,
+    /// Number of classes
+    #[arg(short, long, default_value_t = 3)]
+    pub k: usize,
+    /// Number of tiles along each side of the image
+    #[arg(long, default_value_t = 4)]
+    pub tiles: u32,
+    /// Fraction of the tile size every tile window extends past its tile on each side
+    #[arg(long, default_value_t = 0.25)]
+    pub overlap: f64,
+}
+
+#[derive(Args)]
+pub struct SequenceArgs {
+    /// Metric, as named in the results folder (otsu, kapur, li, ...)
+    #[arg(long, default_value = "otsu")]
+    pub metric: String,
+    /// Search method, as named in the results folder
+    #[arg(long, default_value = "vns")]
+    pub method: String,
+    /// Number of classes
+    #[arg(short, long, default_value_t = 3)]
+    pub k: usize,
+    /// Frame file name with {} in place of the frame number, e.g. frame_{}.png; every image in natural order when omitted
+    #[arg(long)]
+    pub pattern: Option<String>,
+    /// Start every frame from --init or --initial-thresholds instead of the previous frame's thresholds
+    #[arg(long)]
+    pub no_warm_start: bool,
+    /// Smoothing of every threshold over time
+    #[arg(long, value_enum, default_value_t = Smoothing::None)]
+    pub temporal_smoothing: Smoothing,
+    /// Standard deviation of the Gaussian temporal smoothing, in frames
+    #[arg(long, default_value_t = 1.0)]
+    pub temporal_sigma: f64,
+    /// Half width of the temporal moving average, in frames
+    #[arg(long, default_value_t = 1)]
+    pub temporal_radius: usize,
+}
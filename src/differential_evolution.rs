@@ -0,0 +1,117 @@
+use rand::prelude::*;
+
+use crate::search::{self, Evaluator, Metric, SearchConfig, SearchReport, SearchSpace, TracePoint};
+use crate::stats::Histogram;
+
+// Base vector of the mutation: a random member (rand/1/bin) or the best one (best/1/bin)
+#[derive(Clone, Copy)]
+pub enum Variant {
+    Rand1Bin,
+    Best1Bin,
+}
+
+// Round a trial vector to integers and repair it the same way shaking clamps thresholds
+fn repair_trial(space: &SearchSpace, trial: &[f64]) -> Vec<usize> {
+    let mut vector: Vec<usize> = trial.iter().map(|&x| x.round().max(0.0) as usize).collect();
+    space.repair(&mut vector);
+    vector
+}
+
+// Three distinct member indices, all different from `target`
+fn pick_three(rng: &mut StdRng, size: usize, target: usize) -> [usize; 3] {
+    let mut picked = [target; 3];
+    for i in 0..3 {
+        loop {
+            let candidate = rng.gen_range(0..size);
+            if candidate != target && !picked[..i].contains(&candidate) {
+                picked[i] = candidate;
+                break;
+            }
+        }
+    }
+    picked
+}
+
+pub fn differential_evolution(
+    rng: &mut StdRng,
+    histogram: &Histogram,
+    space: &SearchSpace,
+    initial: Option<Vec<usize>>,
+    metric: &Metric,
+    variant: Variant,
+    config: &SearchConfig,
+) -> (Vec<usize>, f64, SearchReport) {
+    let mut evaluator = Evaluator::new(histogram, metric, config);
+    let size = config.population.max(4);
+
+    let mut population: Vec<Vec<usize>> = (0..size).map(|_| space.random(rng)).collect();
+    if let Some(initial) = initial {
+        population[0] = initial;
+    }
+    let mut values: Vec<f64> = population
+        .iter()
+        .map(|member| evaluator.evaluate(member))
+        .collect();
+    let mut best = 0;
+    for i in 1..size {
+        if metric.direction.improves(values[i], values[best]) {
+            best = i;
+        }
+    }
+
+    let mut trace = vec![];
+    let pb = search::progress_bar(config.generations as u64);
+
+    for generation in 0..config.generations {
+        if evaluator.exhausted() {
+            break;
+        }
+        pb.inc(1);
+        for target in 0..size {
+            if evaluator.exhausted() {
+                break;
+            }
+            let [r1, r2, r3] = pick_three(rng, size, target);
+            let base = match variant {
+                Variant::Rand1Bin => r1,
+                Variant::Best1Bin => best,
+            };
+
+            // Mutation and binomial crossover, one dimension always comes from the mutant
+            let forced = rng.gen_range(0..space.dimensions);
+            let trial: Vec<f64> = (0..space.dimensions)
+                .map(|d| {
+                    if d == forced || rng.gen_bool(config.de_crossover) {
+                        population[base][d] as f64
+                            + config.de_weight * (population[r2][d] as f64 - population[r3][d] as f64)
+                    } else {
+                        population[target][d] as f64
+                    }
+                })
+                .collect();
+            let trial = repair_trial(space, &trial);
+            let trial_value = evaluator.evaluate(&trial);
+
+            // Greedy selection, ties go to the trial so the population keeps moving
+            if !metric.direction.improves(values[target], trial_value) {
+                population[target] = trial;
+                values[target] = trial_value;
+                if metric.direction.improves(trial_value, values[best]) {
+                    best = target;
+                }
+            }
+        }
+
+        let mean = values.iter().sum::<f64>() / size as f64;
+        trace.push(TracePoint {
+            iteration: generation + 1,
+            evaluations: evaluator.evaluations,
+            current: mean,
+            best: values[best],
+            parameter: None,
+        });
+    }
+    pb.finish_with_message(format!("Done after {} generations", config.generations));
+
+    (population[best].clone(), values[best], evaluator.report(trace))
+}
@@ -1,13 +1,29 @@
-use std::{fs::OpenOptions, time::Duration};
+use std::{fs::{create_dir_all, read_to_string, File, OpenOptions}, path::Path, time::Duration};
 use std::io::Write;
+
+use serde_json::Value;
+
+use crate::adaptive::Tile;
+use crate::benchmark::Section;
+use crate::morphology::Region;
+use crate::pareto::Solution;
+use crate::search::{SearchReport, TracePoint};
+use crate::stats::Histogram;
+
 pub fn writeln(
     file_path: &str,
     image_name: &str,
     k: usize,
-    best_thresholds: &Vec<usize>,
+    best_thresholds: &[usize],
     duration: Duration,
     objective_value: f64,
+    report: &SearchReport,
 ) {
+    let thresholds = best_thresholds
+        .iter()
+        .map(|x| x.to_string())
+        .collect::<Vec<_>>()
+        .join(";");
     let mut file = OpenOptions::new()
         .append(true)
         .create(true)
@@ -17,12 +33,502 @@ pub fn writeln(
         file,
         "{image_name},{k},{},[{}],{}",
         objective_value,
-        best_thresholds
+        thresholds,
+        duration.as_secs_f64() * 1000.0,
+    )
+    .unwrap();
+
+    // The timing logs keep their five headerless columns, evaluation counts go to a
+    // separate log next to them (otsu_sa_times.csv -> otsu_sa_evaluations.csv)
+    let evaluations_path = format!("{}_evaluations.csv", file_path.trim_end_matches(".csv").trim_end_matches("_times"));
+    let exists = Path::new(&evaluations_path).exists();
+    let mut file = OpenOptions::new()
+        .append(true)
+        .create(true)
+        .open(&evaluations_path)
+        .unwrap();
+    if !exists {
+        writeln!(file, "image,k,objective,thresholds,evaluations,evaluations_to_best").unwrap();
+    }
+    writeln!(
+        file,
+        "{image_name},{k},{},[{}],{},{}",
+        objective_value, thresholds, report.evaluations, report.evaluations_to_best,
+    )
+    .unwrap();
+}
+
+// Seed and refined result of the hybrid method, appended like the timing rows
+pub fn write_refinement(
+    file_path: &str,
+    image_name: &str,
+    k: usize,
+    seed_value: f64,
+    refined_value: f64,
+    refined_thresholds: &[usize],
+) {
+    let mut file = OpenOptions::new()
+        .append(true)
+        .create(true)
+        .open(file_path)
+        .unwrap();
+    writeln!(
+        file,
+        "{image_name},{k},{},{},{},[{}]",
+        seed_value,
+        refined_value,
+        refined_value - seed_value,
+        refined_thresholds
             .iter()
             .map(|x| x.to_string())
             .collect::<Vec<_>>()
             .join(";"),
-        duration.as_secs_f64() * 1000.0,
     )
     .unwrap();
 }
+
+// One row per point of the Pareto front: thresholds then the value of every metric
+pub fn write_pareto_front(file_path: &str, metric_names: &[&str], front: &[Solution]) {
+    let mut file = File::create(file_path).unwrap();
+    writeln!(file, "thresholds,{}", metric_names.join(",")).unwrap();
+    for solution in front {
+        writeln!(
+            file,
+            "[{}],{}",
+            solution
+                .thresholds
+                .iter()
+                .map(|x| x.to_string())
+                .collect::<Vec<_>>()
+                .join(";"),
+            solution
+                .objectives
+                .iter()
+                .map(|x| x.to_string())
+                .collect::<Vec<_>>()
+                .join(","),
+        )
+        .unwrap();
+    }
+}
+
+// One row per k: criterion value, thresholds and whether that k was selected
+pub fn write_auto_k_curve(file_path: &str, criterion_name: &str, curve: &[(usize, Vec<u8>, f64)], selected_k: usize) {
+    let mut file = File::create(file_path).unwrap();
+    writeln!(file, "k,{},thresholds,selected", criterion_name).unwrap();
+    for (k, thresholds, score) in curve {
+        writeln!(
+            file,
+            "{k},{score},[{}],{}",
+            thresholds
+                .iter()
+                .map(|x| x.to_string())
+                .collect::<Vec<_>>()
+                .join(";"),
+            *k == selected_k,
+        )
+        .unwrap();
+    }
+}
+
+// Convergence trace of one run: one row per iteration or generation
+pub fn write_trace(file_path: &str, trace: &[TracePoint]) {
+    if let Some(parent) = Path::new(file_path).parent() {
+        create_dir_all(parent).unwrap();
+    }
+    let mut file = File::create(file_path).unwrap();
+    writeln!(file, "iteration,evaluations,current,best,parameter").unwrap();
+    for point in trace {
+        let parameter = point.parameter.map_or(String::new(), |p| p.to_string());
+        writeln!(
+            file,
+            "{},{},{},{},{}",
+            point.iteration, point.evaluations, point.current, point.best, parameter
+        )
+        .unwrap();
+    }
+}
+
+// Read back a trace written by `write_trace`
+pub fn read_trace(file_path: &str) -> Vec<TracePoint> {
+    let contents = read_to_string(file_path).expect("Failed to read trace file");
+    contents
+        .lines()
+        .skip(1)
+        .map(|line| {
+            let fields: Vec<&str> = line.split(',').collect();
+            TracePoint {
+                iteration: fields[0].parse().expect("Invalid iteration in trace"),
+                evaluations: fields[1].parse().expect("Invalid evaluation count in trace"),
+                current: fields[2].parse().expect("Invalid current value in trace"),
+                best: fields[3].parse().expect("Invalid best value in trace"),
+                parameter: fields[4].parse().ok(),
+            }
+        })
+        .collect()
+}
+
+// Thresholds of the last row of a run log written by `writeln` for this image and k
+pub fn read_result_thresholds(file_path: &Path, image_name: &str, k: usize) -> Option<Vec<usize>> {
+    let contents = read_to_string(file_path)
+        .unwrap_or_else(|_| panic!("Failed to read result file {}", file_path.display()));
+    contents.lines().rev().find_map(|line| {
+        let fields: Vec<&str> = line.split(',').collect();
+        if fields.len() < 4 || fields[0] != image_name || fields[1] != k.to_string() {
+            return None;
+        }
+        let thresholds: Vec<usize> = fields[3]
+            .trim_start_matches('[')
+            .trim_end_matches(']')
+            .split(';')
+            .map(|t| t.parse().ok())
+            .collect::<Option<_>>()?;
+        (thresholds.len() == k - 1).then_some(thresholds)
+    })
+}
+
+// One row per (metric, k, method) of a benchmark
+pub fn write_benchmark_csv(file_path: &str, image_name: &str, sections: &[Section]) {
+    let mut file = File::create(file_path).unwrap();
+    writeln!(
+        file,
+        "image,metric,k,method,optimum,mean,std,best,worst,success_rate,mean_time_ms"
+    )
+    .unwrap();
+    for section in sections {
+        for summary in section.summaries.iter() {
+            writeln!(
+                file,
+                "{image_name},{},{},{},{},{},{},{},{},{},{}",
+                section.metric,
+                section.k,
+                summary.method,
+                section.optimum,
+                summary.mean,
+                summary.std,
+                summary.best,
+                summary.worst,
+                summary.success_rate,
+                summary.mean_time_ms,
+            )
+            .unwrap();
+        }
+    }
+}
+
+// Markdown report of a benchmark: summary table, Friedman test and pairwise Wilcoxon tests per section
+pub fn write_benchmark_markdown(file_path: &str, image_name: &str, runs: u64, sections: &[Section]) {
+    let mut file = File::create(file_path).unwrap();
+    writeln!(file, "# Benchmark of {} ({} runs per method)", image_name, runs).unwrap();
+    for section in sections {
+        writeln!(file).unwrap();
+        writeln!(file, "## {}, k = {} (exhaustive optimum {:.6})", section.metric, section.k, section.optimum).unwrap();
+        writeln!(file).unwrap();
+        writeln!(file, "| method | mean | std | best | worst | success | mean time (ms) |").unwrap();
+        writeln!(file, "|---|---|---|---|---|---|---|").unwrap();
+        for summary in section.summaries.iter() {
+            writeln!(
+                file,
+                "| {} | {:.6} | {:.6} | {:.6} | {:.6} | {:.0}% | {:.2} |",
+                summary.method,
+                summary.mean,
+                summary.std,
+                summary.best,
+                summary.worst,
+                summary.success_rate * 100.0,
+                summary.mean_time_ms,
+            )
+            .unwrap();
+        }
+        writeln!(file).unwrap();
+        match section.friedman {
+            Some((statistic, p_value)) => {
+                writeln!(file, "Friedman: chi2 = {:.4}, p = {:.4}", statistic, p_value).unwrap()
+            }
+            None => writeln!(file, "Friedman: needs at least two methods and two runs").unwrap(),
+        }
+        if !section.wilcoxon.is_empty() {
+            writeln!(file).unwrap();
+            writeln!(file, "| Wilcoxon | W | p |").unwrap();
+            writeln!(file, "|---|---|---|").unwrap();
+            for (a, b, statistic, p_value) in section.wilcoxon.iter() {
+                writeln!(file, "| {} vs {} | {:.1} | {:.4} |", a, b, statistic, p_value).unwrap();
+            }
+        }
+    }
+}
+
+// Input of one run: the image after preprocessing and both preprocessing chains
+pub fn write_run_metadata(
+    file_path: &str,
+    image_name: &str,
+    width: u32,
+    height: u32,
+    image_chain: &str,
+    histogram_chain: &str,
+    seed: u64,
+) {
+    let exists = Path::new(file_path).exists();
+    if let Some(parent) = Path::new(file_path).parent() {
+        create_dir_all(parent).unwrap();
+    }
+    let mut file = OpenOptions::new()
+        .append(true)
+        .create(true)
+        .open(file_path)
+        .unwrap();
+    if !exists {
+        writeln!(file, "image,width,height,image_preprocessing,histogram_preprocessing,seed").unwrap();
+    }
+    writeln!(file, "{},{},{},{},{},{}", image_name, width, height, image_chain, histogram_chain, seed).unwrap();
+}
+
+// Metric, method, k, thresholds and objective of one run on a histogram file
+pub type HistogramResult = (String, String, usize, Vec<u8>, Option<f64>);
+
+// One row per metric/method/k run on a histogram file, the objective is left empty for
+// metrics that cannot be evaluated on the thresholds alone
+pub fn write_histogram_results(file_path: &str, histogram_name: &str, rows: &[HistogramResult]) {
+    let mut file = File::create(file_path).unwrap();
+    writeln!(file, "histogram,metric,method,k,thresholds,objective").unwrap();
+    for (metric, method, k, thresholds, objective) in rows {
+        writeln!(
+            file,
+            "{},{},{},{},[{}],{}",
+            histogram_name,
+            metric,
+            method,
+            k,
+            thresholds
+                .iter()
+                .map(|x| x.to_string())
+                .collect::<Vec<_>>()
+                .join(";"),
+            objective.map(|value| value.to_string()).unwrap_or_default(),
+        )
+        .unwrap();
+    }
+}
+
+// Histogram given as bin index and count pairs, bins 0..=255 with missing bins empty.
+// CSV files hold one "bin,count" line per bin under an optional header, JSON files an
+// array of [bin, count] pairs or of {"bin": .., "count": ..} objects, or a single
+// {"bins": [..], "counts": [..]} object. Errors name what is wrong with the file
+pub fn read_histogram(file_path: &Path) -> Result<Histogram, String> {
+    let contents = read_to_string(file_path).map_err(|error| format!("cannot read the file: {}", error))?;
+    let pairs = if file_path.extension().is_some_and(|ext| ext == "json") {
+        parse_json_bins(&contents)?
+    } else {
+        parse_csv_bins(&contents)?
+    };
+
+    let mut counts = vec![0.0; 256];
+    for (bin, count) in pairs {
+        if bin >= counts.len() {
+            return Err(format!("histogram bin {} is outside 0..=255", bin));
+        }
+        if !count.is_finite() || count < 0.0 {
+            return Err(format!("histogram bin {} has invalid count {}", bin, count));
+        }
+        counts[bin] += count;
+    }
+    if counts.iter().all(|&count| count == 0.0) {
+        return Err("the histogram is empty".to_string());
+    }
+    Ok(Histogram::from_counts(counts))
+}
+
+fn parse_bin(value: f64) -> Result<usize, String> {
+    if value < 0.0 || value.fract() != 0.0 {
+        return Err(format!("invalid histogram bin {}", value));
+    }
+    Ok(value as usize)
+}
+
+fn parse_csv_bins(contents: &str) -> Result<Vec<(usize, f64)>, String> {
+    let mut pairs = vec![];
+    for (line_number, line) in contents.lines().enumerate() {
+        let fields: Vec<&str> = line.split(',').map(|field| field.trim()).collect();
+        if fields.len() < 2 || fields[0].is_empty() {
+            continue;
+        }
+        match (fields[0].parse::<f64>(), fields[1].parse::<f64>()) {
+            (Ok(bin), Ok(count)) => pairs.push((parse_bin(bin)?, count)),
+            // Header
+            _ if line_number == 0 => continue,
+            _ => return Err(format!("invalid histogram line {}: {}", line_number + 1, line)),
+        }
+    }
+    Ok(pairs)
+}
+
+fn json_number(value: &Value, name: &str) -> Result<f64, String> {
+    value
+        .as_f64()
+        .ok_or_else(|| format!("histogram {} {} is not a number", name, value))
+}
+
+fn json_pair(bin: &Value, count: &Value) -> Result<(usize, f64), String> {
+    Ok((parse_bin(json_number(bin, "bin")?)?, json_number(count, "count")?))
+}
+
+fn parse_json_bins(contents: &str) -> Result<Vec<(usize, f64)>, String> {
+    let value: Value = serde_json::from_str(contents).map_err(|error| format!("invalid JSON: {}", error))?;
+    match value {
+        Value::Object(object) => {
+            let array = |key: &str| {
+                object
+                    .get(key)
+                    .and_then(Value::as_array)
+                    .ok_or_else(|| format!("the histogram object has no \"{}\" array", key))
+            };
+            let (bins, counts) = (array("bins")?, array("counts")?);
+            if bins.len() != counts.len() {
+                return Err(format!("{} bins but {} counts", bins.len(), counts.len()));
+            }
+            bins.iter().zip(counts).map(|(bin, count)| json_pair(bin, count)).collect()
+        }
+        Value::Array(entries) => entries
+            .iter()
+            .map(|entry| match entry {
+                Value::Array(pair) if pair.len() == 2 => json_pair(&pair[0], &pair[1]),
+                Value::Object(object) => match (object.get("bin"), object.get("count")) {
+                    (Some(bin), Some(count)) => json_pair(bin, count),
+                    _ => Err(format!("histogram entry {} needs a \"bin\" and a \"count\"", entry)),
+                },
+                _ => Err(format!("histogram entry {} is not a [bin, count] pair", entry)),
+            })
+            .collect(),
+        _ => Err("a JSON histogram is an array of bins or an object of \"bins\" and \"counts\"".to_string()),
+    }
+}
+
+// Every connected region of the segmented image
+pub fn write_regions(file_path: &str, regions: &[Region]) {
+    let mut file = File::create(file_path).unwrap();
+    writeln!(file, "region,class,area,centroid_x,centroid_y,min_x,min_y,max_x,max_y").unwrap();
+    for (index, region) in regions.iter().enumerate() {
+        writeln!(
+            file,
+            "{},{},{},{},{},{},{},{},{}",
+            index,
+            region.class,
+            region.area,
+            region.centroid_x,
+            region.centroid_y,
+            region.min_x,
+            region.min_y,
+            region.max_x,
+            region.max_y,
+        )
+        .unwrap();
+    }
+}
+
+// Number of regions and their total and mean area for every class
+pub fn write_class_regions(file_path: &str, regions: &[Region], classes: usize) {
+    let mut file = File::create(file_path).unwrap();
+    writeln!(file, "class,regions,area,mean_area").unwrap();
+    for class in 0..classes {
+        let areas: Vec<usize> = regions
+            .iter()
+            .filter(|region| region.class as usize == class)
+            .map(|region| region.area)
+            .collect();
+        let area: usize = areas.iter().sum();
+        let mean_area = if areas.is_empty() { 0.0 } else { area as f64 / areas.len() as f64 };
+        writeln!(file, "{},{},{},{}", class, areas.len(), area, mean_area).unwrap();
+    }
+}
+
+// Thresholds of every tile with the window they were computed on
+pub fn write_tile_thresholds(file_path: &str, tiles: &[Tile]) {
+    let mut file = File::create(file_path).unwrap();
+    writeln!(file, "column,row,x,y,width,height,thresholds").unwrap();
+    for tile in tiles {
+        writeln!(
+            file,
+            "{},{},{},{},{},{},[{}]",
+            tile.column,
+            tile.row,
+            tile.x,
+            tile.y,
+            tile.width,
+            tile.height,
+            tile.thresholds.iter().map(|t| t.to_string()).collect::<Vec<_>>().join(";")
+        )
+        .unwrap();
+    }
+}
+
+// Thresholds of every frame in order, as found and after temporal smoothing
+pub fn write_sequence_thresholds(file_path: &str, names: &[String], raw: &[Vec<u8>], smoothed: &[Vec<u8>]) {
+    let format = |thresholds: &[u8]| thresholds.iter().map(|t| t.to_string()).collect::<Vec<_>>().join(";");
+    let mut file = File::create(file_path).unwrap();
+    writeln!(file, "frame,image,thresholds,smoothed_thresholds").unwrap();
+    for (frame, ((name, raw), smoothed)) in names.iter().zip(raw).zip(smoothed).enumerate() {
+        writeln!(file, "{},{},[{}],[{}]", frame, name, format(raw), format(smoothed)).unwrap();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    // Writes the contents to a file of the temporary directory and reads it back as a histogram
+    fn read_back(file_name: &str, contents: &str) -> Result<Histogram, String> {
+        let path = std::env::temp_dir().join(format!("assignment_2_{}_{}", std::process::id(), file_name));
+        fs::write(&path, contents).unwrap();
+        let histogram = read_histogram(&path);
+        fs::remove_file(&path).unwrap();
+        histogram
+    }
+
+    fn expected_counts() -> Vec<f64> {
+        let mut counts = vec![0.0; 256];
+        counts[0] = 3.0;
+        counts[17] = 2.5;
+        counts[255] = 1.0;
+        counts
+    }
+
+    #[test]
+    fn every_format_reads_the_same_histogram() {
+        let files = [
+            ("header.csv", "bin,count\n0,3\n17,2.5\n255,1\n"),
+            ("plain.csv", "0, 3\n17, 2.5\n\n255, 1\n"),
+            ("pairs.json", "[[0, 3], [17, 2.5], [255, 1]]"),
+            ("objects.json", r#"[{"bin": 0, "count": 3}, {"bin": 17, "count": 2.5}, {"bin": 255, "count": 1}]"#),
+            ("columns.json", r#"{"bins": [0, 17, 255], "counts": [3, 2.5, 1]}"#),
+        ];
+        for (file_name, contents) in files {
+            let histogram = read_back(file_name, contents).unwrap_or_else(|error| panic!("{}: {}", file_name, error));
+            assert_eq!(histogram.counts, expected_counts(), "{}", file_name);
+            assert_eq!(histogram.total, 6.5, "{}", file_name);
+        }
+    }
+
+    #[test]
+    fn repeated_bins_add_up() {
+        let histogram = read_back("repeated.csv", "0,1\n0,2\n17,2.5\n255,1\n").unwrap();
+        assert_eq!(histogram.counts, expected_counts());
+    }
+
+    #[test]
+    fn bad_files_are_errors() {
+        let files = [
+            ("range.csv", "256,1\n"),
+            ("fraction.csv", "1.5,1\n"),
+            ("negative.json", "[[0, -1]]"),
+            ("empty.csv", "bin,count\n0,0\n"),
+            ("line.csv", "0,1\nseventeen,2\n"),
+            ("syntax.json", "[[0, 1]"),
+            ("columns.json", r#"{"bins": [0, 1], "counts": [1]}"#),
+            ("entry.json", r#"[{"bin": 0}]"#),
+        ];
+        for (file_name, contents) in files {
+            assert!(read_back(file_name, contents).is_err(), "{}", file_name);
+        }
+    }
+}
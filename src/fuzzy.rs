@@ -0,0 +1,283 @@
+use std::time::Instant;
+
+use rand::{rngs::StdRng, SeedableRng};
+
+use crate::search::{self, AnnealingSchedule, Direction, Metric, SearchConfig, SearchSpace};
+use crate::runner::{self, RunLog};
+use crate::stats::Histogram;
+
+// Fuzzy entropy with trapezoidal memberships, higher is better
+pub const FUZZY_TRAPEZOID: Metric = Metric {
+    objective: calculate_trapezoid_fuzzy_entropy,
+    direction: Direction::Maximise,
+};
+
+// Fuzzy entropy with S-shaped (Zadeh S-function) memberships, higher is better
+pub const FUZZY_S: Metric = Metric {
+    objective: calculate_s_fuzzy_entropy,
+    direction: Direction::Maximise,
+};
+
+// Interval type-2 fuzzy entropy built on the trapezoidal memberships, higher is better
+pub const FUZZY_TYPE2: Metric = Metric {
+    objective: calculate_type2_fuzzy_entropy,
+    direction: Direction::Maximise,
+};
+
+// Simulated annealing parameters, the temperature is on the scale of the entropy
+const ANNEALING_SCHEDULE: AnnealingSchedule = AnnealingSchedule {
+    initial_temperature: 1.0,
+    cooling_rate: 0.995,
+    min_temperature: 0.0,
+    max_iterations: 100_000,
+    max_no_improvement: 500,
+};
+
+// Every threshold is the crossover point of a membership edge running from a to c,
+// so k classes are described by the 2(k - 1) parameters a1 < c1 < a2 < c2 < ...
+fn membership_space(k: usize) -> SearchSpace {
+    SearchSpace {
+        dimensions: 2 * (k - 1),
+        lower: 1,
+        upper: 254,
+        ordered: true,
+        groups: 1,
+    }
+}
+
+// A configured initialisation gives thresholds, every one becomes the narrowest edge around it
+fn initial_parameters(
+    config: &SearchConfig,
+    image_name: &str,
+    histogram: &Histogram,
+    k: usize,
+    rng: &mut StdRng,
+) -> Option<Vec<usize>> {
+    let thresholds = search::initial_thresholds(config, image_name, histogram, k, rng)?;
+    let space = membership_space(k);
+    let mut parameters: Vec<usize> = thresholds.iter().flat_map(|&t| [t, t + 1]).collect();
+    space.repair(&mut parameters);
+    Some(parameters)
+}
+
+// Thresholds sit halfway along each edge, where the neighbouring memberships are both 0.5
+pub fn parameters_to_thresholds(parameters: &[usize]) -> Vec<usize> {
+    parameters
+        .chunks(2)
+        .map(|edge| (edge[0] + edge[1]) / 2)
+        .collect()
+}
+
+pub fn compute_trapezoid_fuzzy_thresholds_simulated_annealing(image_name: &str, histogram: &Histogram, k: usize, config: &SearchConfig) -> Vec<u8> {
+    compute_fuzzy_thresholds_simulated_annealing(image_name, histogram, k, &FUZZY_TRAPEZOID, "fuzzy_trapezoid", config)
+}
+
+pub fn compute_trapezoid_fuzzy_thresholds_variable_neighborhood(image_name: &str, histogram: &Histogram, k: usize, config: &SearchConfig) -> Vec<u8> {
+    compute_fuzzy_thresholds_variable_neighborhood(image_name, histogram, k, &FUZZY_TRAPEZOID, "fuzzy_trapezoid", config)
+}
+
+pub fn compute_s_fuzzy_thresholds_simulated_annealing(image_name: &str, histogram: &Histogram, k: usize, config: &SearchConfig) -> Vec<u8> {
+    compute_fuzzy_thresholds_simulated_annealing(image_name, histogram, k, &FUZZY_S, "fuzzy_s", config)
+}
+
+pub fn compute_s_fuzzy_thresholds_variable_neighborhood(image_name: &str, histogram: &Histogram, k: usize, config: &SearchConfig) -> Vec<u8> {
+    compute_fuzzy_thresholds_variable_neighborhood(image_name, histogram, k, &FUZZY_S, "fuzzy_s", config)
+}
+
+pub fn compute_type2_fuzzy_thresholds_simulated_annealing(image_name: &str, histogram: &Histogram, k: usize, config: &SearchConfig) -> Vec<u8> {
+    compute_fuzzy_thresholds_simulated_annealing(image_name, histogram, k, &FUZZY_TYPE2, "fuzzy_type2", config)
+}
+
+pub fn compute_type2_fuzzy_thresholds_variable_neighborhood(image_name: &str, histogram: &Histogram, k: usize, config: &SearchConfig) -> Vec<u8> {
+    compute_fuzzy_thresholds_variable_neighborhood(image_name, histogram, k, &FUZZY_TYPE2, "fuzzy_type2", config)
+}
+
+fn compute_fuzzy_thresholds_simulated_annealing(
+    image_name: &str,
+    histogram: &Histogram,
+    k: usize,
+    metric: &Metric,
+    metric_name: &str,
+    config: &SearchConfig,
+) -> Vec<u8> {
+    let start_time = Instant::now();
+    if k < 2 {
+        panic!("The number of classes 'k' must be at least 2.");
+    }
+
+    // Initialize membership parameters
+    let space = membership_space(k);
+    let mut rng = StdRng::seed_from_u64(config.seed);
+    let parameters =
+        initial_parameters(config, image_name, histogram, k, &mut rng).unwrap_or_else(|| space.random(&mut rng));
+
+    let (best_parameters, max_entropy, report) =
+        search::simulated_annealing(&mut rng, histogram, &space, parameters, metric, &ANNEALING_SCHEDULE, config);
+    let best_thresholds = parameters_to_thresholds(&best_parameters);
+
+    let run = RunLog {
+        metric_name,
+        metric,
+        method_name: "sa",
+        image_name,
+        k,
+    };
+    runner::record_run(&run, histogram, &best_thresholds, max_entropy, &report, start_time.elapsed(), config);
+
+    println!("Optimal membership parameters (fuzzy entropy with SA): {:?}", best_parameters);
+    println!("Optimal thresholds (fuzzy entropy with SA): {:?}", best_thresholds);
+
+    // Convert thresholds to u8
+    best_thresholds.iter().map(|&t| t as u8).collect()
+}
+
+fn compute_fuzzy_thresholds_variable_neighborhood(
+    image_name: &str,
+    histogram: &Histogram,
+    k: usize,
+    metric: &Metric,
+    metric_name: &str,
+    config: &SearchConfig,
+) -> Vec<u8> {
+    let start_time = Instant::now();
+    if k < 2 {
+        panic!("The number of classes 'k' must be at least 2.");
+    }
+
+    // Initialize membership parameters to equally spaced values, unless an initialisation is configured
+    let space = membership_space(k);
+    let mut rng = StdRng::seed_from_u64(config.seed);
+    let parameters =
+        initial_parameters(config, image_name, histogram, k, &mut rng).unwrap_or_else(|| space.evenly_spaced());
+    let (best_parameters, max_entropy, report) =
+        search::variable_neighborhood(&mut rng, histogram, &space, parameters, metric, config);
+    let best_thresholds = parameters_to_thresholds(&best_parameters);
+
+    let run = RunLog {
+        metric_name,
+        metric,
+        method_name: "vns",
+        image_name,
+        k,
+    };
+    runner::record_run(&run, histogram, &best_thresholds, max_entropy, &report, start_time.elapsed(), config);
+
+    println!("Optimal membership parameters (fuzzy entropy with VNS): {:?}", best_parameters);
+    println!("Optimal thresholds (fuzzy entropy with VNS): {:?}", best_thresholds);
+
+    // Convert thresholds to u8
+    best_thresholds.iter().map(|&t| t as u8).collect()
+}
+
+// Linear edge: 0 up to a, 1 from c on
+fn trapezoid_edge(x: f64, a: f64, c: f64) -> f64 {
+    if x <= a {
+        0.0
+    } else if x >= c {
+        1.0
+    } else {
+        (x - a) / (c - a)
+    }
+}
+
+// Zadeh S-function: 0 up to a, 1 from c on, quadratic in between with 0.5 at the midpoint
+fn s_edge(x: f64, a: f64, c: f64) -> f64 {
+    if x <= a {
+        0.0
+    } else if x >= c {
+        1.0
+    } else if x <= (a + c) / 2.0 {
+        2.0 * ((x - a) / (c - a)).powi(2)
+    } else {
+        1.0 - 2.0 * ((x - c) / (c - a)).powi(2)
+    }
+}
+
+pub fn calculate_trapezoid_fuzzy_entropy(histogram: &Histogram, parameters: &[usize], intensity_levels: usize) -> f64 {
+    calculate_fuzzy_entropy(histogram, parameters, intensity_levels, trapezoid_edge, 1.0)
+}
+
+pub fn calculate_s_fuzzy_entropy(histogram: &Histogram, parameters: &[usize], intensity_levels: usize) -> f64 {
+    calculate_fuzzy_entropy(histogram, parameters, intensity_levels, s_edge, 1.0)
+}
+
+// The footprint of uncertainty of every class runs from the lower membership mu^2
+// to the upper membership mu^0.5, and the entropy is the mean over both bounds
+pub fn calculate_type2_fuzzy_entropy(histogram: &Histogram, parameters: &[usize], intensity_levels: usize) -> f64 {
+    let lower = calculate_fuzzy_entropy(histogram, parameters, intensity_levels, trapezoid_edge, 2.0);
+    let upper = calculate_fuzzy_entropy(histogram, parameters, intensity_levels, trapezoid_edge, 0.5);
+    (lower + upper) / 2.0
+}
+
+// Membership of grey level i in class j. Class j rises along edge j - 1 and falls along
+// edge j, so the memberships of a level add up to one
+fn membership(parameters: &[usize], j: usize, i: usize, edge: fn(f64, f64, f64) -> f64) -> f64 {
+    let classes = parameters.len() / 2 + 1;
+    let x = i as f64;
+    let rise = if j == 0 {
+        1.0
+    } else {
+        edge(x, parameters[2 * j - 2] as f64, parameters[2 * j - 1] as f64)
+    };
+    let fall = if j == classes - 1 {
+        0.0
+    } else {
+        edge(x, parameters[2 * j] as f64, parameters[2 * j + 1] as f64)
+    };
+    (rise - fall).max(0.0)
+}
+
+// Sum over classes of the entropy of the membership weighted histogram
+fn calculate_fuzzy_entropy(
+    histogram: &Histogram,
+    parameters: &[usize],
+    intensity_levels: usize,
+    edge: fn(f64, f64, f64) -> f64,
+    power: f64,
+) -> f64 {
+    let classes = parameters.len() / 2 + 1;
+    let mut total_entropy = 0.0;
+
+    for j in 0..classes {
+        // Memberships are zero outside the two edges of the class
+        let start = if j == 0 { 0 } else { parameters[2 * j - 2] };
+        let end = if j == classes - 1 { intensity_levels } else { parameters[2 * j + 1] + 1 };
+
+        let weighted: Vec<f64> = (start..end)
+            .map(|i| histogram.prob[i] * membership(parameters, j, i, edge).powf(power))
+            .collect();
+        let class_prob: f64 = weighted.iter().sum();
+
+        if class_prob > 0.0 {
+            let mut entropy = 0.0;
+            for &w in weighted.iter() {
+                let p = w / class_prob;
+                if p > 0.0 {
+                    entropy -= p * p.ln();
+                }
+            }
+            total_entropy += entropy;
+        }
+    }
+    total_entropy
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn memberships_sum_to_one() {
+        // Three classes, the second edge is a single step
+        let parameters = [40, 90, 150, 151];
+        for edge in [trapezoid_edge as fn(f64, f64, f64) -> f64, s_edge] {
+            for i in 0..256 {
+                let total: f64 = (0..3).map(|j| membership(&parameters, j, i, edge)).sum();
+                assert!((total - 1.0).abs() < 1e-12, "level {} sums to {}", i, total);
+            }
+        }
+        // Halfway along an edge both neighbouring classes hold one half
+        assert_eq!(membership(&parameters, 0, 65, trapezoid_edge), 0.5);
+        assert_eq!(membership(&parameters, 1, 65, s_edge), 0.5);
+    }
+}
@@ -0,0 +1,145 @@
+use rand::prelude::*;
+
+use crate::search::{self, Evaluator, Metric, Neighbourhood, SearchConfig, SearchReport, SearchSpace, TracePoint};
+use crate::stats::Histogram;
+
+// Crossover that keeps children valid: for ordered spaces every group of the child takes
+// a random sorted selection out of both parents' values of that group merged together,
+// so it stays sorted and only needs duplicates spread out. Unordered spaces use uniform crossover
+pub fn order_preserving_crossover(rng: &mut StdRng, space: &SearchSpace, a: &[usize], b: &[usize]) -> Vec<usize> {
+    let len = space.group_len();
+    let mut child = if space.ordered {
+        a.chunks(len)
+            .zip(b.chunks(len))
+            .flat_map(|(a, b)| {
+                let mut merged: Vec<usize> = a.iter().chain(b.iter()).copied().collect();
+                merged.sort_unstable();
+                let mut picked = rand::seq::index::sample(rng, merged.len(), len).into_vec();
+                picked.sort_unstable();
+                picked.iter().map(|&i| merged[i]).collect::<Vec<usize>>()
+            })
+            .collect()
+    } else {
+        a.iter()
+            .zip(b.iter())
+            .map(|(&x, &y)| if rng.gen_bool(0.5) { x } else { y })
+            .collect::<Vec<usize>>()
+    };
+    space.repair(&mut child);
+    child
+}
+
+// Mutation reuses the ±1, ±3 and random replacement moves of the VNS shaking
+pub fn mutate(rng: &mut StdRng, space: &SearchSpace, individual: &[usize]) -> Vec<usize> {
+    let neighbourhood = [Neighbourhood::Step(1), Neighbourhood::Step(3), Neighbourhood::Block(1)][rng.gen_range(0..3)];
+    search::shaking(rng, space, individual, neighbourhood)
+}
+
+// Index of the best of `size` randomly drawn individuals
+fn tournament(rng: &mut StdRng, values: &[f64], size: usize, metric: &Metric) -> usize {
+    let mut winner = rng.gen_range(0..values.len());
+    for _ in 1..size {
+        let challenger = rng.gen_range(0..values.len());
+        if metric.direction.improves(values[challenger], values[winner]) {
+            winner = challenger;
+        }
+    }
+    winner
+}
+
+pub fn genetic_algorithm(
+    rng: &mut StdRng,
+    histogram: &Histogram,
+    space: &SearchSpace,
+    initial: Option<Vec<usize>>,
+    metric: &Metric,
+    config: &SearchConfig,
+) -> (Vec<usize>, f64, SearchReport) {
+    let mut evaluator = Evaluator::new(histogram, metric, config);
+    let mut trace = vec![];
+
+    let mut population: Vec<Vec<usize>> = (0..config.population).map(|_| space.random(rng)).collect();
+    if let Some(initial) = initial {
+        population[0] = initial;
+    }
+    let mut values: Vec<f64> = population
+        .iter()
+        .map(|individual| evaluator.evaluate(individual))
+        .collect();
+
+    // Best individual seen so far, which without elitism may not survive its generation
+    let mut best = 0;
+    for i in 1..population.len() {
+        if metric.direction.improves(values[i], values[best]) {
+            best = i;
+        }
+    }
+    let mut best_individual = population[best].clone();
+    let mut best_value = values[best];
+
+    let pb = search::progress_bar(config.generations as u64);
+
+    for generation in 0..config.generations {
+        if evaluator.exhausted() {
+            break;
+        }
+        pb.inc(1);
+
+        // Elitism: the best individuals survive unchanged
+        let mut order: Vec<usize> = (0..population.len()).collect();
+        order.sort_by(|&a, &b| {
+            if metric.direction.improves(values[a], values[b]) {
+                std::cmp::Ordering::Less
+            } else if metric.direction.improves(values[b], values[a]) {
+                std::cmp::Ordering::Greater
+            } else {
+                std::cmp::Ordering::Equal
+            }
+        });
+        let elites = &order[..config.elitism.min(config.population)];
+        let mut next_population: Vec<Vec<usize>> = elites.iter().map(|&i| population[i].clone()).collect();
+        let mut next_values: Vec<f64> = elites.iter().map(|&i| values[i]).collect();
+
+        while next_population.len() < config.population {
+            // Out of budget mid-generation: the current population fills the gap unchanged
+            if evaluator.exhausted() {
+                for &i in order.iter().skip(elites.len()).take(config.population - next_population.len()) {
+                    next_population.push(population[i].clone());
+                    next_values.push(values[i]);
+                }
+                break;
+            }
+            let a = tournament(rng, &values, config.tournament_size, metric);
+            let b = tournament(rng, &values, config.tournament_size, metric);
+            let mut child = if rng.gen_bool(config.crossover_rate) {
+                order_preserving_crossover(rng, space, &population[a], &population[b])
+            } else {
+                population[a].clone()
+            };
+            if rng.gen_bool(config.mutation_rate) {
+                child = mutate(rng, space, &child);
+            }
+            let value = evaluator.evaluate(&child);
+            if metric.direction.improves(value, best_value) {
+                best_individual = child.clone();
+                best_value = value;
+            }
+            next_values.push(value);
+            next_population.push(child);
+        }
+
+        population = next_population;
+        values = next_values;
+
+        trace.push(TracePoint {
+            iteration: generation + 1,
+            evaluations: evaluator.evaluations,
+            current: values.iter().sum::<f64>() / values.len() as f64,
+            best: best_value,
+            parameter: None,
+        });
+    }
+    pb.finish_with_message(format!("Done after {} generations", config.generations));
+
+    (best_individual, best_value, evaluator.report(trace))
+}
@@ -0,0 +1,89 @@
+use rand::prelude::*;
+
+use crate::search::{self, Evaluator, Metric, SearchConfig, SearchReport, SearchSpace, TracePoint};
+use crate::stats::Histogram;
+
+pub fn harmony_search(
+    rng: &mut StdRng,
+    histogram: &Histogram,
+    space: &SearchSpace,
+    initial: Option<Vec<usize>>,
+    metric: &Metric,
+    config: &SearchConfig,
+) -> (Vec<usize>, f64, SearchReport) {
+    let mut evaluator = Evaluator::new(histogram, metric, config);
+    let size = config.harmony_memory.max(1);
+    let bandwidth = config.bandwidth as isize;
+
+    let mut memory: Vec<Vec<usize>> = (0..size).map(|_| space.random(rng)).collect();
+    if let Some(initial) = initial {
+        memory[0] = initial;
+    }
+    let mut values: Vec<f64> = memory
+        .iter()
+        .map(|harmony| evaluator.evaluate(harmony))
+        .collect();
+
+    let mut trace = vec![];
+    let pb = search::progress_bar(config.hs_iterations as u64);
+
+    for iteration in 0..config.hs_iterations {
+        if evaluator.exhausted() {
+            break;
+        }
+        pb.inc(1);
+
+        // Improvise a new harmony from memory, pitch adjustment or a random pick
+        let mut harmony: Vec<usize> = (0..space.dimensions)
+            .map(|d| {
+                if rng.gen_bool(config.harmony_rate) {
+                    let mut value = memory[rng.gen_range(0..size)][d] as isize;
+                    if rng.gen_bool(config.pitch_rate) {
+                        value += rng.gen_range(-bandwidth..=bandwidth);
+                    }
+                    value.clamp(space.lower as isize, space.upper as isize) as usize
+                } else {
+                    rng.gen_range(space.lower..=space.upper)
+                }
+            })
+            .collect();
+        space.repair(&mut harmony);
+        let value = evaluator.evaluate(&harmony);
+
+        // Replace the worst harmony in memory if the new one beats it
+        let mut worst = 0;
+        let mut best = 0;
+        for i in 1..size {
+            if metric.direction.improves(values[worst], values[i]) {
+                worst = i;
+            }
+            if metric.direction.improves(values[i], values[best]) {
+                best = i;
+            }
+        }
+        if metric.direction.improves(value, values[worst]) {
+            memory[worst] = harmony;
+            values[worst] = value;
+            if metric.direction.improves(value, values[best]) {
+                best = worst;
+            }
+        }
+
+        trace.push(TracePoint {
+            iteration: iteration + 1,
+            evaluations: evaluator.evaluations,
+            current: value,
+            best: values[best],
+            parameter: None,
+        });
+    }
+    pb.finish_with_message(format!("Done after {} improvisations", config.hs_iterations));
+
+    let mut best = 0;
+    for i in 1..size {
+        if metric.direction.improves(values[i], values[best]) {
+            best = i;
+        }
+    }
+    (memory[best].clone(), values[best], evaluator.report(trace))
+}
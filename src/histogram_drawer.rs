@@ -1,28 +1,29 @@
-use image::GrayImage;
 use plotters::prelude::*;
 
+use crate::stats::{Histogram, JointHistogram};
+
+// Bin counts to plot, with the zero level emptied when it is excluded
+fn plotted_counts(histogram: &Histogram, exclude_zero: bool) -> Vec<f64> {
+    let mut counts = histogram.counts.clone();
+    if exclude_zero {
+        counts[0] = 0.0;
+    }
+    counts
+}
+
 pub fn save_histogram(
     image_name: &str,
-    gray_img: &GrayImage,
+    histogram: &Histogram,
     output_path: &str,
     exlude_zero: bool,
 ) {
-    // Compute the histogram
-    let mut histogram = [0u32; 256];
-
-    for pixel in gray_img.pixels() {
-        if exlude_zero && pixel[0] == 0 {
-            continue;
-        }
-        let intensity = pixel[0] as usize;
-        histogram[intensity] += 1;
-    }
+    let counts = plotted_counts(histogram, exlude_zero);
 
     // Plot the histogram
     let root = BitMapBackend::new(output_path, (640, 480)).into_drawing_area();
     root.fill(&WHITE).unwrap();
 
-    let max_count = *histogram.iter().max().unwrap();
+    let max_count = counts.iter().cloned().fold(0.0, f64::max);
     let caption = if exlude_zero {
         format!("Histogram of {} (excluding zeros)", image_name)
     } else {
@@ -33,18 +34,16 @@ pub fn save_histogram(
         .margin(20)
         .x_label_area_size(40)
         .y_label_area_size(40)
-        .build_cartesian_2d(0u32..255u32, 0u32..(max_count + max_count / 10))
+        .build_cartesian_2d(0u32..counts.len() as u32 - 1, 0.0..(max_count * 1.1).max(1.0))
         .unwrap();
 
     chart.configure_mesh().draw().unwrap();
 
     chart
-        .draw_series(histogram.iter().enumerate().map(|(x, y)| {
+        .draw_series(counts.iter().enumerate().map(|(x, y)| {
             let x0 = x as u32;
             let x1 = x0 + 1;
-            let y0 = 0u32;
-            let y1 = *y;
-            Rectangle::new([(x0, y0), (x1, y1)], BLUE.mix(0.5).filled())
+            Rectangle::new([(x0, 0.0), (x1, *y)], BLUE.mix(0.5).filled())
         }))
         .unwrap();
 
@@ -55,49 +54,97 @@ pub fn save_histogram(
 }
 
 
+// Raw histogram as bars with the preprocessed one drawn over it as a line
+pub fn save_histogram_comparison(
+    image_name: &str,
+    raw: &Histogram,
+    processed: &Histogram,
+    output_path: &str,
+    exclude_zero: bool,
+) {
+    let raw_counts = plotted_counts(raw, exclude_zero);
+    let processed_counts = plotted_counts(processed, exclude_zero);
+
+    let root = BitMapBackend::new(output_path, (640, 480)).into_drawing_area();
+    root.fill(&WHITE).unwrap();
+
+    let max_count = raw_counts.iter().chain(&processed_counts).cloned().fold(0.0, f64::max);
+    let caption = format!("Raw and preprocessed histogram of {}", image_name);
+    let mut chart = ChartBuilder::on(&root)
+        .caption(caption, ("sans-serif", 20).into_font())
+        .margin(20)
+        .x_label_area_size(40)
+        .y_label_area_size(40)
+        .build_cartesian_2d(0u32..raw_counts.len() as u32 - 1, 0.0..(max_count * 1.1).max(1.0))
+        .unwrap();
+
+    chart.configure_mesh().draw().unwrap();
+
+    chart
+        .draw_series(raw_counts.iter().enumerate().map(|(x, y)| {
+            let x0 = x as u32;
+            let x1 = x0 + 1;
+            Rectangle::new([(x0, 0.0), (x1, *y)], BLUE.mix(0.3).filled())
+        }))
+        .unwrap()
+        .label("raw")
+        .legend(|(x, y)| Rectangle::new([(x, y - 5), (x + 20, y + 5)], BLUE.mix(0.3).filled()));
+
+    chart
+        .draw_series(LineSeries::new(
+            processed_counts.iter().enumerate().map(|(x, y)| (x as u32, *y)),
+            RED.stroke_width(2),
+        ))
+        .unwrap()
+        .label("preprocessed")
+        .legend(|(x, y)| PathElement::new(vec![(x, y), (x + 20, y)], RED.stroke_width(2)));
+
+    chart
+        .configure_series_labels()
+        .background_style(WHITE.mix(0.8))
+        .border_style(BLACK)
+        .draw()
+        .unwrap();
+
+    // Ensure the output is saved
+    root.present().unwrap();
+
+    println!("Histogram comparison saved to {}", output_path);
+}
+
+
 pub fn draw_histogram_with_thresholds(
     image_name: &str,
     method_name: &str,
     search_name:&str,
-    gray_img: &GrayImage,
+    histogram: &Histogram,
     output_path: &str,
     thresholds: &Vec<u8>,
     exclude_zero: bool
 ) {
-
-    let mut histogram = [0u32; 256];
-
-    for pixel in gray_img.pixels() {
-        if exclude_zero && pixel[0] == 0 {
-            continue;
-        }
-        let intensity = pixel[0] as usize;
-        histogram[intensity] += 1;
-    }
+    let counts = plotted_counts(histogram, exclude_zero);
 
     let root = BitMapBackend::new(output_path, (640, 480)).into_drawing_area();
     root.fill(&WHITE).unwrap();
 
     let caption = format!("Histogram of {} segmented with {} using {}", image_name, method_name, search_name);
-    let max_count = *histogram.iter().max().unwrap_or(&0);
+    let max_count = counts.iter().cloned().fold(0.0, f64::max);
 
     let mut chart = ChartBuilder::on(&root)
         .caption(caption, ("sans-serif", 20).into_font())
         .margin(20)
         .x_label_area_size(40)
         .y_label_area_size(40)
-        .build_cartesian_2d(0u32..255u32, 0u32..(max_count + max_count / 10))
+        .build_cartesian_2d(0u32..counts.len() as u32 - 1, 0.0..(max_count * 1.1).max(1.0))
         .unwrap();
 
     chart.configure_mesh().draw().unwrap();
 
     chart
-        .draw_series(histogram.iter().enumerate().map(|(x, y)| {
+        .draw_series(counts.iter().enumerate().map(|(x, y)| {
             let x0 = x as u32;
             let x1 = x0 + 1;
-            let y0 = 0u32;
-            let y1 = *y;
-            Rectangle::new([(x0, y0), (x1, y1)], BLUE.mix(0.5).filled())
+            Rectangle::new([(x0, 0.0), (x1, *y)], BLUE.mix(0.5).filled())
         }))
         .unwrap();
 
@@ -105,7 +152,7 @@ pub fn draw_histogram_with_thresholds(
     for &threshold in thresholds {
         chart
             .draw_series(LineSeries::new(
-                vec![(threshold as u32, 0), (threshold as u32, max_count)],
+                vec![(threshold as u32, 0.0), (threshold as u32, max_count)],
                 &RED,
             ))
             .unwrap();
@@ -118,3 +165,291 @@ pub fn draw_histogram_with_thresholds(
 }
 
 
+// Heatmap of the 2D histogram on a log scale, intensity along x and neighbourhood mean along y,
+// with a vertical line at every intensity threshold and a horizontal one at every mean threshold
+pub fn draw_joint_histogram(
+    image_name: &str,
+    method_name: &str,
+    search_name: &str,
+    joint: &JointHistogram,
+    output_path: &str,
+    intensity_thresholds: &[usize],
+    mean_thresholds: &[usize],
+) {
+    let root = BitMapBackend::new(output_path, (640, 640)).into_drawing_area();
+    root.fill(&WHITE).unwrap();
+
+    let caption = format!("2D histogram of {} segmented with {} using {}", image_name, method_name, search_name);
+    let levels = JointHistogram::LEVELS as u32;
+
+    let mut chart = ChartBuilder::on(&root)
+        .caption(caption, ("sans-serif", 20).into_font())
+        .margin(20)
+        .x_label_area_size(40)
+        .y_label_area_size(40)
+        .build_cartesian_2d(0u32..levels, 0u32..levels)
+        .unwrap();
+
+    chart
+        .configure_mesh()
+        .disable_mesh()
+        .x_desc("intensity")
+        .y_desc("neighbourhood mean")
+        .draw()
+        .unwrap();
+
+    // Colour from blue for the rarest pairs to red for the most frequent
+    let max_prob = joint.prob.iter().cloned().fold(0.0, f64::max);
+    let min_prob = joint.prob.iter().cloned().filter(|&p| p > 0.0).fold(max_prob, f64::min);
+    let span = (max_prob / min_prob).ln().max(1e-12);
+    chart
+        .draw_series(joint.prob.iter().enumerate().filter(|(_, &p)| p > 0.0).map(|(index, &p)| {
+            let x = (index / JointHistogram::LEVELS) as u32;
+            let y = (index % JointHistogram::LEVELS) as u32;
+            let scale = (p / min_prob).ln() / span;
+            Rectangle::new([(x, y), (x + 1, y + 1)], HSLColor(0.66 * (1.0 - scale), 0.9, 0.5).filled())
+        }))
+        .unwrap();
+
+    // Draw threshold lines
+    for &threshold in intensity_thresholds {
+        chart
+            .draw_series(LineSeries::new(vec![(threshold as u32, 0), (threshold as u32, levels)], &BLACK))
+            .unwrap();
+    }
+    for &threshold in mean_thresholds {
+        chart
+            .draw_series(LineSeries::new(vec![(0, threshold as u32), (levels, threshold as u32)], &BLACK))
+            .unwrap();
+    }
+
+    // Ensure the output is saved
+    root.present().unwrap();
+}
+
+
+pub fn draw_pareto_front(
+    image_name: &str,
+    k: usize,
+    x_name: &str,
+    y_name: &str,
+    front: &[(f64, f64)],
+    output_path: &str,
+) {
+    let root = BitMapBackend::new(output_path, (640, 480)).into_drawing_area();
+    root.fill(&WHITE).unwrap();
+
+    let caption = format!("Pareto front of {} for k={}", image_name, k);
+
+    // Pad the axes so that points on the boundary stay visible
+    let (x_min, x_max) = front.iter().fold((f64::MAX, f64::MIN), |(lo, hi), p| (lo.min(p.0), hi.max(p.0)));
+    let (y_min, y_max) = front.iter().fold((f64::MAX, f64::MIN), |(lo, hi), p| (lo.min(p.1), hi.max(p.1)));
+    let x_pad = ((x_max - x_min) / 10.0).max(1e-6);
+    let y_pad = ((y_max - y_min) / 10.0).max(1e-6);
+
+    let mut chart = ChartBuilder::on(&root)
+        .caption(caption, ("sans-serif", 20).into_font())
+        .margin(20)
+        .x_label_area_size(40)
+        .y_label_area_size(60)
+        .build_cartesian_2d((x_min - x_pad)..(x_max + x_pad), (y_min - y_pad)..(y_max + y_pad))
+        .unwrap();
+
+    chart
+        .configure_mesh()
+        .x_desc(x_name)
+        .y_desc(y_name)
+        .draw()
+        .unwrap();
+
+    chart
+        .draw_series(front.iter().map(|&(x, y)| Circle::new((x, y), 3, RED.filled())))
+        .unwrap();
+
+    // Ensure the output is saved
+    root.present().unwrap();
+
+    println!("Pareto front saved to {}", output_path);
+}
+
+pub fn draw_auto_k_curve(
+    image_name: &str,
+    criterion_name: &str,
+    curve: &[(usize, f64)],
+    selected_k: usize,
+    output_path: &str,
+) {
+    let root = BitMapBackend::new(output_path, (640, 480)).into_drawing_area();
+    root.fill(&WHITE).unwrap();
+
+    let caption = format!("{} of {} against k", criterion_name, image_name);
+
+    let k_max = curve.iter().map(|p| p.0).max().unwrap_or(2) as u32;
+    let (y_min, y_max) = curve.iter().fold((f64::MAX, f64::MIN), |(lo, hi), p| (lo.min(p.1), hi.max(p.1)));
+    let y_pad = ((y_max - y_min) / 10.0).max(1e-6);
+
+    let mut chart = ChartBuilder::on(&root)
+        .caption(caption, ("sans-serif", 20).into_font())
+        .margin(20)
+        .x_label_area_size(40)
+        .y_label_area_size(60)
+        .build_cartesian_2d(2u32..k_max, (y_min - y_pad)..(y_max + y_pad))
+        .unwrap();
+
+    chart.configure_mesh().x_desc("k").y_desc(criterion_name).draw().unwrap();
+
+    chart
+        .draw_series(LineSeries::new(curve.iter().map(|&(k, y)| (k as u32, y)), &BLUE))
+        .unwrap();
+    chart
+        .draw_series(curve.iter().map(|&(k, y)| {
+            let color = if k == selected_k { RED } else { BLUE };
+            Circle::new((k as u32, y), 4, color.filled())
+        }))
+        .unwrap();
+
+    // Ensure the output is saved
+    root.present().unwrap();
+
+    println!("Criterion curve saved to {}", output_path);
+}
+
+// Best-so-far curves of repeated runs, with the median and the interquartile band across runs.
+// Runs that stopped early keep their final value up to the longest run
+pub fn draw_convergence(
+    image_name: &str,
+    label: &str,
+    runs: &[Vec<f64>],
+    output_path: &str,
+) {
+    let root = BitMapBackend::new(output_path, (640, 480)).into_drawing_area();
+    root.fill(&WHITE).unwrap();
+
+    let caption = format!("Convergence of {} on {} ({} runs)", label, image_name, runs.len());
+
+    let length = runs.iter().map(|run| run.len()).max().unwrap_or(0).max(2);
+    let padded: Vec<Vec<f64>> = runs
+        .iter()
+        .filter(|run| !run.is_empty())
+        .map(|run| (0..length).map(|i| run[i.min(run.len() - 1)]).collect())
+        .collect();
+
+    // Quartiles across runs at every iteration
+    let quartile = |values: &[f64], q: f64| values[((values.len() - 1) as f64 * q).round() as usize];
+    let mut lower = Vec::with_capacity(length);
+    let mut median = Vec::with_capacity(length);
+    let mut upper = Vec::with_capacity(length);
+    for i in 0..length {
+        let mut values: Vec<f64> = padded.iter().map(|run| run[i]).collect();
+        if values.is_empty() {
+            break;
+        }
+        values.sort_by(|a, b| a.partial_cmp(b).unwrap_or(std::cmp::Ordering::Equal));
+        lower.push(quartile(&values, 0.25));
+        median.push(quartile(&values, 0.5));
+        upper.push(quartile(&values, 0.75));
+    }
+
+    let (y_min, y_max) = padded
+        .iter()
+        .flatten()
+        .fold((f64::MAX, f64::MIN), |(lo, hi), &y| (lo.min(y), hi.max(y)));
+    let (y_min, y_max) = if y_min > y_max { (0.0, 1.0) } else { (y_min, y_max) };
+    let y_pad = ((y_max - y_min) / 10.0).max(1e-6);
+
+    let mut chart = ChartBuilder::on(&root)
+        .caption(caption, ("sans-serif", 20).into_font())
+        .margin(20)
+        .x_label_area_size(40)
+        .y_label_area_size(60)
+        .build_cartesian_2d(1usize..length, (y_min - y_pad)..(y_max + y_pad))
+        .unwrap();
+
+    chart.configure_mesh().x_desc("Iteration").y_desc("Best objective").draw().unwrap();
+
+    // Interquartile band
+    let band: Vec<(usize, f64)> = upper
+        .iter()
+        .enumerate()
+        .map(|(i, &y)| (i + 1, y))
+        .chain(lower.iter().enumerate().rev().map(|(i, &y)| (i + 1, y)))
+        .collect();
+    chart.draw_series(std::iter::once(Polygon::new(band, BLUE.mix(0.2)))).unwrap();
+
+    for run in padded.iter() {
+        chart
+            .draw_series(LineSeries::new(run.iter().enumerate().map(|(i, &y)| (i + 1, y)), BLACK.mix(0.2)))
+            .unwrap();
+    }
+    chart
+        .draw_series(LineSeries::new(median.iter().enumerate().map(|(i, &y)| (i + 1, y)), BLUE.stroke_width(2)))
+        .unwrap();
+
+    // Ensure the output is saved
+    root.present().unwrap();
+
+    println!("Convergence plot saved to {}", output_path);
+}
+
+// Every threshold against the frame index, the smoothed thresholds drawn over the raw ones when given
+pub fn draw_thresholds_over_time(
+    label: &str,
+    raw: &[Vec<u8>],
+    smoothed: Option<&[Vec<u8>]>,
+    output_path: &str,
+) {
+    let root = BitMapBackend::new(output_path, (640, 480)).into_drawing_area();
+    root.fill(&WHITE).unwrap();
+
+    let caption = format!("Thresholds over time of {} ({} frames)", label, raw.len());
+    let frames = raw.len().max(2) as u32;
+
+    let mut chart = ChartBuilder::on(&root)
+        .caption(caption, ("sans-serif", 20).into_font())
+        .margin(20)
+        .x_label_area_size(40)
+        .y_label_area_size(40)
+        .build_cartesian_2d(0u32..frames - 1, 0u32..255u32)
+        .unwrap();
+
+    chart.configure_mesh().x_desc("frame").y_desc("threshold").draw().unwrap();
+
+    let thresholds = raw.first().map_or(0, |frame| frame.len());
+    for i in 0..thresholds {
+        let color = Palette99::pick(i).to_rgba();
+        let raw_series = raw.iter().enumerate().map(|(frame, t)| (frame as u32, t[i] as u32));
+        match smoothed {
+            Some(smoothed) => {
+                chart
+                    .draw_series(LineSeries::new(raw_series, color.mix(0.3)))
+                    .unwrap();
+                chart
+                    .draw_series(LineSeries::new(
+                        smoothed.iter().enumerate().map(|(frame, t)| (frame as u32, t[i] as u32)),
+                        color.stroke_width(2),
+                    ))
+                    .unwrap()
+                    .label(format!("threshold {}", i + 1))
+                    .legend(move |(x, y)| PathElement::new(vec![(x, y), (x + 20, y)], color));
+            }
+            None => {
+                chart
+                    .draw_series(LineSeries::new(raw_series, color.stroke_width(2)))
+                    .unwrap()
+                    .label(format!("threshold {}", i + 1))
+                    .legend(move |(x, y)| PathElement::new(vec![(x, y), (x + 20, y)], color));
+            }
+        }
+    }
+    chart
+        .configure_series_labels()
+        .background_style(WHITE.mix(0.8))
+        .border_style(BLACK)
+        .draw()
+        .unwrap();
+
+    // Ensure the output is saved
+    root.present().unwrap();
+
+    println!("Thresholds over time saved to {}", output_path);
+}
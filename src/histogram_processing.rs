@@ -0,0 +1,186 @@
+use clap::{Args, ValueEnum};
+
+use crate::stats::Histogram;
+
+// Smoothing applied to the bin counts
+#[derive(Clone, Copy, Debug, PartialEq, ValueEnum)]
+pub enum Smoothing {
+    /// Raw counts
+    None,
+    /// Gaussian kernel of standard deviation --smoothing-sigma
+    Gaussian,
+    /// Mean over 2 * --smoothing-radius + 1 bins
+    MovingAverage,
+}
+
+// Preprocessing of the histogram before it is optimised, applied as clipping,
+// then rebinning, then smoothing
+#[derive(Args, Clone)]
+pub struct HistogramConfig {
+    /// Histogram: counts above this quantile of the non-empty bins are clipped to it, e.g. 0.99
+    #[arg(long, global = true)]
+    pub clip_quantile: Option<f64>,
+    /// Histogram: number of consecutive levels pooled into one bin, whose count is shared evenly among them
+    #[arg(long, global = true, default_value_t = 1)]
+    pub bin_width: usize,
+    /// Histogram: smoothing of the counts
+    #[arg(long, global = true, value_enum, default_value_t = Smoothing::None)]
+    pub smoothing: Smoothing,
+    /// Histogram: standard deviation of the Gaussian smoothing, in levels
+    #[arg(long, global = true, default_value_t = 1.5)]
+    pub smoothing_sigma: f64,
+    /// Histogram: half width of the moving average, in levels
+    #[arg(long, global = true, default_value_t = 2)]
+    pub smoothing_radius: usize,
+}
+
+impl HistogramConfig {
+    // True when preprocessing leaves the histogram unchanged
+    pub fn is_identity(&self) -> bool {
+        self.clip_quantile.is_none() && self.bin_width <= 1 && self.smoothing == Smoothing::None
+    }
+
+    // Steps in the order they are applied, "none" when there are none
+    pub fn describe(&self) -> String {
+        let mut steps = vec![];
+        if let Some(quantile) = self.clip_quantile {
+            steps.push(format!("clip:{}", quantile));
+        }
+        if self.bin_width > 1 {
+            steps.push(format!("rebin:{}", self.bin_width));
+        }
+        match self.smoothing {
+            Smoothing::None => {}
+            Smoothing::Gaussian => steps.push(format!("gaussian:{}", self.smoothing_sigma)),
+            Smoothing::MovingAverage => steps.push(format!("moving_average:{}", self.smoothing_radius)),
+        }
+        if steps.is_empty() {
+            "none".to_string()
+        } else {
+            steps.join(";")
+        }
+    }
+}
+
+pub fn preprocess(histogram: &Histogram, config: &HistogramConfig) -> Histogram {
+    let mut counts = histogram.counts.clone();
+    if let Some(quantile) = config.clip_quantile {
+        counts = clip(&counts, quantile);
+    }
+    if config.bin_width > 1 {
+        counts = rebin(&counts, config.bin_width);
+    }
+    counts = match config.smoothing {
+        Smoothing::None => counts,
+        Smoothing::Gaussian => gaussian_smoothing(&counts, config.smoothing_sigma),
+        Smoothing::MovingAverage => moving_average(&counts, config.smoothing_radius),
+    };
+    // The joint histogram keeps the raw image statistics
+    let mut processed = Histogram::from_counts(counts);
+    processed.joint = histogram.joint.clone();
+    processed
+}
+
+// Caps spikes at the given quantile of the non-empty bin counts
+pub fn clip(counts: &[f64], quantile: f64) -> Vec<f64> {
+    if !(0.0..=1.0).contains(&quantile) {
+        panic!("The clipping quantile must lie in 0..=1.");
+    }
+    let mut non_empty: Vec<f64> = counts.iter().cloned().filter(|&c| c > 0.0).collect();
+    if non_empty.is_empty() {
+        return counts.to_vec();
+    }
+    non_empty.sort_by(|a, b| a.partial_cmp(b).unwrap());
+    let limit = non_empty[((non_empty.len() - 1) as f64 * quantile).round() as usize];
+    counts.iter().map(|&c| c.min(limit)).collect()
+}
+
+// Pools every group of `width` levels and spreads the pooled count evenly back over the
+// group, so the histogram keeps one bin per grey level
+pub fn rebin(counts: &[f64], width: usize) -> Vec<f64> {
+    counts
+        .chunks(width)
+        .flat_map(|group| {
+            let mean = group.iter().sum::<f64>() / group.len() as f64;
+            std::iter::repeat_n(mean, group.len())
+        })
+        .collect()
+}
+
+// Weighted mean of the neighbouring bins, the weights of the bins past either end are
+// dropped and the rest renormalised so that the edges are not pulled towards zero
+fn convolve(counts: &[f64], kernel: &[f64]) -> Vec<f64> {
+    let radius = (kernel.len() / 2) as isize;
+    (0..counts.len() as isize)
+        .map(|i| {
+            let mut sum = 0.0;
+            let mut weight = 0.0;
+            for (offset, &w) in kernel.iter().enumerate() {
+                let j = i + offset as isize - radius;
+                if j >= 0 && (j as usize) < counts.len() {
+                    sum += w * counts[j as usize];
+                    weight += w;
+                }
+            }
+            sum / weight
+        })
+        .collect()
+}
+
+pub fn gaussian_smoothing(counts: &[f64], sigma: f64) -> Vec<f64> {
+    if sigma <= 0.0 {
+        panic!("The smoothing sigma must be positive.");
+    }
+    let radius = (3.0 * sigma).ceil() as isize;
+    let kernel: Vec<f64> = (-radius..=radius)
+        .map(|x| (-0.5 * (x as f64 / sigma).powi(2)).exp())
+        .collect();
+    convolve(counts, &kernel)
+}
+
+pub fn moving_average(counts: &[f64], radius: usize) -> Vec<f64> {
+    convolve(counts, &vec![1.0; 2 * radius + 1])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rebin_spreads_each_pooled_count_over_its_levels() {
+        let counts = [1.0, 2.0, 3.0, 4.0, 5.0];
+        let rebinned = rebin(&counts, 2);
+        assert_eq!(rebinned, vec![1.5, 1.5, 3.5, 3.5, 5.0]);
+        assert_eq!(rebinned.iter().sum::<f64>(), counts.iter().sum::<f64>());
+        assert_eq!(rebin(&counts, 1), counts.to_vec());
+    }
+
+    #[test]
+    fn clip_caps_at_the_quantile_of_non_empty_bins() {
+        let counts = [0.0, 1.0, 2.0, 3.0, 10.0];
+        assert_eq!(clip(&counts, 0.5), vec![0.0, 1.0, 2.0, 3.0, 3.0]);
+        assert_eq!(clip(&counts, 1.0), counts.to_vec());
+    }
+
+    #[test]
+    fn moving_average_renormalises_at_the_edges() {
+        assert_eq!(moving_average(&[0.0, 0.0, 3.0, 0.0, 0.0], 1), vec![0.0, 1.0, 1.0, 1.0, 0.0]);
+        assert_eq!(moving_average(&[3.0, 0.0, 0.0], 1), vec![1.5, 1.0, 0.0]);
+    }
+
+    #[test]
+    fn gaussian_smoothing_keeps_flat_histograms_and_symmetric_peaks() {
+        let flat = gaussian_smoothing(&[4.0; 20], 1.5);
+        assert!(flat.iter().all(|&c| (c - 4.0).abs() < 1e-12));
+
+        let mut peak = vec![0.0; 21];
+        peak[10] = 1.0;
+        // The kernel reaches 3 sigma to either side
+        let smoothed = gaussian_smoothing(&peak, 2.0);
+        for distance in 1..=6 {
+            assert!((smoothed[10 - distance] - smoothed[10 + distance]).abs() < 1e-12);
+            assert!(smoothed[10 - distance] < smoothed[11 - distance]);
+        }
+        assert_eq!(smoothed[3], 0.0);
+    }
+}
@@ -0,0 +1,277 @@
+use std::fmt;
+use std::str::FromStr;
+
+use image::imageops::{self, FilterType};
+use image::{GrayImage, Luma};
+
+// One step of the image preprocessing chain, applied to the grey-level image before
+// its histogram is built
+#[derive(Clone, Debug, PartialEq)]
+pub enum ImageStep {
+    // Median over a (2r + 1) x (2r + 1) window
+    Median(u32),
+    // Gaussian blur of standard deviation sigma
+    Gaussian(f32),
+    // Linear stretch of the low..high percentiles of the grey levels onto 0..=255
+    Stretch(f64, f64),
+    // Contrast limited adaptive histogram equalisation on a tiles x tiles grid, with the
+    // bins clipped at `clip` times their mean count
+    Clahe(u32, f64),
+    // Both sides divided by the factor
+    Downscale(f64),
+    // Rectangle x, y, width, height
+    Crop(u32, u32, u32, u32),
+}
+
+impl fmt::Display for ImageStep {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ImageStep::Median(radius) => write!(f, "median:{}", radius),
+            ImageStep::Gaussian(sigma) => write!(f, "gaussian:{}", sigma),
+            ImageStep::Stretch(low, high) => write!(f, "stretch:{}:{}", low, high),
+            ImageStep::Clahe(tiles, clip) => write!(f, "clahe:{}:{}", tiles, clip),
+            ImageStep::Downscale(factor) => write!(f, "downscale:{}", factor),
+            ImageStep::Crop(x, y, width, height) => write!(f, "crop:{}:{}:{}:{}", x, y, width, height),
+        }
+    }
+}
+
+fn parse_field<T: FromStr>(value: &str, step: &str) -> Result<T, String> {
+    value
+        .parse()
+        .map_err(|_| format!("invalid value '{}' in preprocessing step '{}'", value, step))
+}
+
+impl FromStr for ImageStep {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let fields: Vec<&str> = s.split(':').collect();
+        let step = match (fields[0], fields.len()) {
+            ("median", 2) => ImageStep::Median(parse_field(fields[1], s)?),
+            ("gaussian", 2) => ImageStep::Gaussian(parse_field(fields[1], s)?),
+            ("stretch", 1) => ImageStep::Stretch(1.0, 99.0),
+            ("stretch", 3) => ImageStep::Stretch(parse_field(fields[1], s)?, parse_field(fields[2], s)?),
+            ("clahe", 3) => ImageStep::Clahe(parse_field(fields[1], s)?, parse_field(fields[2], s)?),
+            ("downscale", 2) => ImageStep::Downscale(parse_field(fields[1], s)?),
+            ("crop", 5) => ImageStep::Crop(
+                parse_field(fields[1], s)?,
+                parse_field(fields[2], s)?,
+                parse_field(fields[3], s)?,
+                parse_field(fields[4], s)?,
+            ),
+            _ => {
+                return Err(format!(
+                    "unknown preprocessing step '{}', expected median:r, gaussian:sigma, stretch[:low:high], \
+                     clahe:tiles:clip, downscale:factor or crop:x:y:w:h",
+                    s
+                ))
+            }
+        };
+        match step {
+            ImageStep::Gaussian(sigma) if sigma <= 0.0 => Err(format!("'{}' needs a positive sigma", s)),
+            ImageStep::Stretch(low, high) if !(0.0 <= low && low < high && high <= 100.0) => {
+                Err(format!("'{}' needs percentiles 0 <= low < high <= 100", s))
+            }
+            ImageStep::Clahe(tiles, clip) if tiles == 0 || clip < 1.0 => {
+                Err(format!("'{}' needs at least one tile and a clip limit of at least 1", s))
+            }
+            ImageStep::Downscale(factor) if factor < 1.0 => Err(format!("'{}' needs a factor of at least 1", s)),
+            ImageStep::Crop(_, _, width, height) if width == 0 || height == 0 => {
+                Err(format!("'{}' needs a non-empty rectangle", s))
+            }
+            step => Ok(step),
+        }
+    }
+}
+
+// The chain as written on the command line, "none" when it is empty
+pub fn describe(steps: &[ImageStep]) -> String {
+    if steps.is_empty() {
+        return "none".to_string();
+    }
+    steps.iter().map(|step| step.to_string()).collect::<Vec<_>>().join(";")
+}
+
+pub fn apply_chain(gray_img: GrayImage, steps: &[ImageStep]) -> GrayImage {
+    steps.iter().fold(gray_img, |img, step| apply_step(&img, step))
+}
+
+pub fn apply_step(gray_img: &GrayImage, step: &ImageStep) -> GrayImage {
+    match *step {
+        ImageStep::Median(radius) => median_filter(gray_img, radius),
+        ImageStep::Gaussian(sigma) => imageops::blur(gray_img, sigma),
+        ImageStep::Stretch(low, high) => contrast_stretch(gray_img, low, high),
+        ImageStep::Clahe(tiles, clip) => clahe(gray_img, tiles, clip),
+        ImageStep::Downscale(factor) => {
+            let width = ((gray_img.width() as f64 / factor).round() as u32).max(1);
+            let height = ((gray_img.height() as f64 / factor).round() as u32).max(1);
+            imageops::resize(gray_img, width, height, FilterType::Triangle)
+        }
+        ImageStep::Crop(x, y, width, height) => {
+            if x + width > gray_img.width() || y + height > gray_img.height() {
+                panic!(
+                    "Crop {}x{} at ({}, {}) does not fit in the {}x{} image",
+                    width,
+                    height,
+                    x,
+                    y,
+                    gray_img.width(),
+                    gray_img.height()
+                );
+            }
+            imageops::crop_imm(gray_img, x, y, width, height).to_image()
+        }
+    }
+}
+
+fn level_counts(gray_img: &GrayImage) -> [u64; 256] {
+    let mut counts = [0u64; 256];
+    for pixel in gray_img.pixels() {
+        counts[pixel[0] as usize] += 1;
+    }
+    counts
+}
+
+// Window clamped at the borders, the median is read off the running window histogram
+// (Huang's algorithm): along a row the window histogram only loses its leftmost column and
+// gains a new rightmost one, instead of being rebuilt for every pixel
+fn median_filter(gray_img: &GrayImage, radius: u32) -> GrayImage {
+    let (width, height) = gray_img.dimensions();
+    let mut filtered = GrayImage::new(width, height);
+    for y in 0..height {
+        let (top, bottom) = (y.saturating_sub(radius), (y + radius).min(height - 1));
+        let column = |counts: &mut [i64; 256], x: u32, change: i64| {
+            for ny in top..=bottom {
+                counts[gray_img.get_pixel(x, ny)[0] as usize] += change;
+            }
+        };
+
+        let mut counts = [0i64; 256];
+        for x in 0..=radius.min(width - 1) {
+            column(&mut counts, x, 1);
+        }
+        for x in 0..width {
+            let size = ((x + radius).min(width - 1) - x.saturating_sub(radius) + 1) * (bottom - top + 1);
+            let mut seen = 0;
+            let median = (0..256)
+                .find(|&level| {
+                    seen += counts[level];
+                    2 * seen > size as i64
+                })
+                .unwrap_or(255);
+            filtered.put_pixel(x, y, Luma([median as u8]));
+
+            // Slide the window one pixel to the right
+            if x >= radius {
+                column(&mut counts, x - radius, -1);
+            }
+            if x + radius + 1 < width {
+                column(&mut counts, x + radius + 1, 1);
+            }
+        }
+    }
+    filtered
+}
+
+// Smallest grey level below which at least `percentile` percent of the pixels lie
+fn percentile_level(counts: &[u64; 256], total: u64, percentile: f64) -> usize {
+    let target = (percentile / 100.0 * total as f64).ceil() as u64;
+    let mut seen = 0;
+    for (level, &count) in counts.iter().enumerate() {
+        seen += count;
+        if seen >= target.max(1) {
+            return level;
+        }
+    }
+    255
+}
+
+fn contrast_stretch(gray_img: &GrayImage, low: f64, high: f64) -> GrayImage {
+    let counts = level_counts(gray_img);
+    let total = gray_img.width() as u64 * gray_img.height() as u64;
+    let low_level = percentile_level(&counts, total, low) as f64;
+    let high_level = percentile_level(&counts, total, high) as f64;
+    if high_level <= low_level {
+        return gray_img.clone();
+    }
+    let mut stretched = gray_img.clone();
+    for pixel in stretched.pixels_mut() {
+        let value = (pixel[0] as f64 - low_level) / (high_level - low_level) * 255.0;
+        pixel[0] = value.round().clamp(0.0, 255.0) as u8;
+    }
+    stretched
+}
+
+// Equalisation mapping of one tile, with the excess over the clip limit spread evenly
+// over all levels before the cumulative histogram is taken
+fn clahe_mapping(gray_img: &GrayImage, x0: u32, y0: u32, x1: u32, y1: u32, clip: f64) -> [f64; 256] {
+    let mut counts = [0.0; 256];
+    for y in y0..y1 {
+        for x in x0..x1 {
+            counts[gray_img.get_pixel(x, y)[0] as usize] += 1.0;
+        }
+    }
+    let total = ((x1 - x0) * (y1 - y0)) as f64;
+    let limit = clip * total / 256.0;
+    let excess: f64 = counts.iter().map(|&c| (c - limit).max(0.0)).sum();
+    for count in counts.iter_mut() {
+        *count = count.min(limit) + excess / 256.0;
+    }
+
+    let mut mapping = [0.0; 256];
+    let mut cumulative = 0.0;
+    for (level, &count) in counts.iter().enumerate() {
+        cumulative += count;
+        mapping[level] = cumulative / total * 255.0;
+    }
+    mapping
+}
+
+// Tiles whose centres surround a coordinate along one axis and the weight of the second one,
+// past the outer centres both are the nearest tile
+pub fn tile_neighbours(position: f64, centres: &[f64]) -> (usize, usize, f64) {
+    let next = centres.iter().position(|&c| c > position).unwrap_or(centres.len());
+    if next == 0 {
+        (0, 0, 0.0)
+    } else if next == centres.len() {
+        (next - 1, next - 1, 0.0)
+    } else {
+        let weight = (position - centres[next - 1]) / (centres[next] - centres[next - 1]);
+        (next - 1, next, weight)
+    }
+}
+
+// Every pixel is mapped by the four surrounding tile mappings, weighted bilinearly by
+// its distance to their centres
+fn clahe(gray_img: &GrayImage, tiles: u32, clip: f64) -> GrayImage {
+    let (width, height) = gray_img.dimensions();
+    let tiles_x = tiles.min(width);
+    let tiles_y = tiles.min(height);
+    let bounds = |tile: u32, tiles: u32, size: u32| (tile * size / tiles, (tile + 1) * size / tiles);
+
+    let mut mappings = vec![];
+    let mut centres_x = vec![];
+    let mut centres_y = vec![];
+    for ty in 0..tiles_y {
+        let (y0, y1) = bounds(ty, tiles_y, height);
+        centres_y.push((y0 + y1) as f64 / 2.0);
+        for tx in 0..tiles_x {
+            let (x0, x1) = bounds(tx, tiles_x, width);
+            if ty == 0 {
+                centres_x.push((x0 + x1) as f64 / 2.0);
+            }
+            mappings.push(clahe_mapping(gray_img, x0, y0, x1, y1, clip));
+        }
+    }
+
+    GrayImage::from_fn(width, height, |x, y| {
+        let level = gray_img.get_pixel(x, y)[0] as usize;
+        let (left, right, wx) = tile_neighbours(x as f64 + 0.5, &centres_x);
+        let (top, bottom, wy) = tile_neighbours(y as f64 + 0.5, &centres_y);
+        let at = |tx: usize, ty: usize| mappings[ty * tiles_x as usize + tx][level];
+        let upper = at(left, top) * (1.0 - wx) + at(right, top) * wx;
+        let lower = at(left, bottom) * (1.0 - wx) + at(right, bottom) * wx;
+        Luma([(upper * (1.0 - wy) + lower * wy).round().clamp(0.0, 255.0) as u8])
+    })
+}
@@ -0,0 +1,73 @@
+use crate::runner::{Method, ThresholdMetric};
+use crate::search::{AnnealingSchedule, Direction, Metric};
+use crate::stats::{self, Histogram};
+
+// Kittler and Illingworth's minimum error criterion, lower is better
+pub const KITTLER: Metric = Metric {
+    objective: calculate_minimum_error,
+    direction: Direction::Minimise,
+};
+
+// Simulated annealing parameters, shared with the SA seed of the hybrid method,
+// the temperature is on the scale of the error criterion
+const ANNEALING_SCHEDULE: AnnealingSchedule = AnnealingSchedule {
+    initial_temperature: 1.0,
+    cooling_rate: 0.995,
+    min_temperature: 0.0,
+    max_iterations: 100_000,
+    max_no_improvement: 500,
+};
+
+pub const KITTLER_THRESHOLDS: ThresholdMetric = ThresholdMetric {
+    name: "kittler",
+    label: "Kittler-Illingworth",
+    metric: &KITTLER,
+    schedule: &ANNEALING_SCHEDULE,
+    random_annealing_start: true,
+    methods: &Method::SINGLE_SOLUTION,
+};
+
+// Minimum error criterion for a mixture of Gaussian classes:
+//   J = 1 + 2 sum_c P_c (ln sigma_c - ln P_c)
+// Classes holding a single grey level have zero variance, so the variance is
+// floored at 1/12, the variance of a grey level quantised to an integer
+pub fn calculate_minimum_error(histogram: &Histogram, thresholds: &[usize], intensity_levels: usize) -> f64 {
+    let classes = stats::calculate_class_statistics(histogram, thresholds, intensity_levels);
+
+    let mut error = 1.0;
+    for (i, &p) in classes.prob.iter().enumerate() {
+        if p > 0.0 {
+            let variance = classes.variance[i].max(1.0 / 12.0);
+            error += p * variance.ln() - 2.0 * p * p.ln();
+        }
+    }
+    error
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::search;
+
+    // Equal spikes at grey levels 50 and 200
+    fn two_spikes() -> Histogram {
+        let mut counts = vec![0.0; 256];
+        counts[50] = 100.0;
+        counts[200] = 100.0;
+        Histogram::from_counts(counts)
+    }
+
+    #[test]
+    fn two_spikes_are_split_at_the_floored_variance() {
+        let histogram = two_spikes();
+        // Two classes of weight 1/2 and variance 1/12: J = 1 + ln(1/12) - 2 ln(1/2) = 1 - ln 3
+        let split = 1.0 - 3f64.ln();
+        assert!((calculate_minimum_error(&histogram, &[120], 256) - split).abs() < 1e-9);
+        // One class with both spikes has a variance of 75^2
+        assert!((calculate_minimum_error(&histogram, &[30], 256) - (1.0 + 5625f64.ln())).abs() < 1e-9);
+
+        let (thresholds, value, _) = search::exhaustive(&histogram, 2, &KITTLER);
+        assert!((51..=200).contains(&thresholds[0]), "threshold {:?}", thresholds);
+        assert!((value - split).abs() < 1e-9);
+    }
+}
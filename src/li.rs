@@ -0,0 +1,84 @@
+use crate::runner::{Method, ThresholdMetric};
+use crate::search::{AnnealingSchedule, Direction, Metric};
+use crate::stats::Histogram;
+
+// Li and Lee's minimum cross-entropy criterion, lower is better
+pub const LI: Metric = Metric {
+    objective: calculate_cross_entropy,
+    direction: Direction::Minimise,
+};
+
+// Simulated annealing parameters, shared with the SA seed of the hybrid method,
+// the temperature is on the scale of the cross entropy
+const ANNEALING_SCHEDULE: AnnealingSchedule = AnnealingSchedule {
+    initial_temperature: 1.0,
+    cooling_rate: 0.995,
+    min_temperature: 0.0,
+    max_iterations: 100_000,
+    max_no_improvement: 500,
+};
+
+pub const LI_THRESHOLDS: ThresholdMetric = ThresholdMetric {
+    name: "li",
+    label: "Li's method",
+    metric: &LI,
+    schedule: &ANNEALING_SCHEDULE,
+    random_annealing_start: true,
+    methods: &Method::SINGLE_SOLUTION,
+};
+
+// Cross entropy between the image and its thresholded version, where every
+// pixel is replaced by the mean of its class:
+//   sum_i i p(i) ln(i / mu(class of i))
+// Grey level i is counted as i + 1 so that black pixels contribute too
+pub fn calculate_cross_entropy(histogram: &Histogram, thresholds: &[usize], intensity_levels: usize) -> f64 {
+    let mut cross_entropy = 0.0;
+    let mut start = 0;
+
+    for &threshold in thresholds.iter().chain(std::iter::once(&intensity_levels)) {
+        // Levels are shifted by one, which adds the class weight to its first moment
+        let class_count = histogram.cumulative_counts[threshold] - histogram.cumulative_counts[start];
+        let class_prob = class_count / histogram.total;
+        let class_moment =
+            (histogram.cumulative_moment[threshold] - histogram.cumulative_moment[start] + class_count) / histogram.total;
+
+        if class_prob > 0.0 {
+            let class_mean = class_moment / class_prob;
+            for i in start..threshold {
+                let p = histogram.prob[i];
+                if p > 0.0 {
+                    let level = (i + 1) as f64;
+                    cross_entropy += level * p * (level / class_mean).ln();
+                }
+            }
+        }
+        start = threshold;
+    }
+    cross_entropy
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::search;
+
+    // Equal spikes at grey levels 50 and 200
+    fn two_spikes() -> Histogram {
+        let mut counts = vec![0.0; 256];
+        counts[50] = 100.0;
+        counts[200] = 100.0;
+        Histogram::from_counts(counts)
+    }
+
+    #[test]
+    fn two_spikes_are_split_without_cross_entropy() {
+        let histogram = two_spikes();
+        // Every class holds a single level, which is its own mean
+        assert!(calculate_cross_entropy(&histogram, &[120], 256).abs() < 1e-9);
+        assert!(calculate_cross_entropy(&histogram, &[30], 256) > 0.1);
+
+        let (thresholds, value, _) = search::exhaustive(&histogram, 2, &LI);
+        assert!((51..=200).contains(&thresholds[0]), "threshold {:?}", thresholds);
+        assert!(value.abs() < 1e-9);
+    }
+}
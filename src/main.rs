@@ -1,33 +1,120 @@
+use clap::error::ErrorKind;
+use clap::{CommandFactory, Parser};
 use image::{GenericImageView, GrayImage, Luma};
+use rand::{rngs::StdRng, SeedableRng};
 use rfd::FileDialog;
 use std::fs;
 use std::path::{Path, PathBuf};
+use std::time::Instant;
 
+use cli::{AutoKArgs, BenchmarkArgs, Cli, Command, ConvergenceArgs, HistogramArgs, ParetoArgs, SequenceArgs, TiledArgs};
+use runner::{available_methods, find_method, THRESHOLD_METRICS};
+use search::{Metric, SearchConfig, SearchSpace};
+use morphology::SegmentationConfig;
+use stats::Histogram;
+
+mod adaptive;
+mod auto_k;
+mod benchmark;
+mod cli;
+mod differential_evolution;
 mod file_writing;
+mod fuzzy;
+mod genetic;
+mod harmony_search;
 mod histogram_drawer;
+mod histogram_processing;
+mod image_processing;
 mod kapur;
+mod kittler;
+mod li;
+mod morphology;
+mod optimum_cache;
 mod otsu;
+mod pareto;
+mod pso;
+mod runner;
+mod search;
+mod sequence;
+mod significance;
+mod spatial;
 mod stats;
 
 fn run() -> Result<(), Box<dyn std::error::Error>> {
-    
+    let mut cli = Cli::parse();
+    let command = cli.command.take().unwrap_or(Command::Run);
+    if let Command::Histogram(args) = &command {
+        check_histogram_pairs(args);
+    }
+
     // Directory containing the images
-    println!("Select the folder containing the images");
-    let img_dir = FileDialog::new()
-        .set_directory("/")
-        .pick_folder()
-        .expect("No folder selected")
-        .to_str()
-        .expect("Failed to convert path to string")
-        .to_string();
+    let img_dir = match cli.input.take() {
+        Some(dir) => dir,
+        None => {
+            println!("Select the folder containing the images");
+            FileDialog::new()
+                .set_directory("/")
+                .pick_folder()
+                .expect("No folder selected")
+        }
+    };
+    // The commands run at a single k reject initial thresholds that do not fit it before any
+    // image is read, the others fall back to the default initialisation for the other k
+    let single_k = match &command {
+        Command::Pareto(args) => Some(args.k),
+        Command::Convergence(args) => Some(args.k),
+        Command::Tiled(args) => Some(args.k),
+        Command::Sequence(args) => Some(args.k),
+        Command::Run | Command::AutoK(_) | Command::Benchmark(_) | Command::Histogram(_) => None,
+    };
+    if let (Some(k), Some(thresholds)) = (single_k, &cli.search.initial_thresholds) {
+        if thresholds.len() + 1 != k {
+            return Err(format!("{} initial thresholds were given but k={} needs {}", thresholds.len(), k, k.saturating_sub(1)).into());
+        }
+    }
+
     // Create necessary directories
     let dirs = [
         "results/otsu/exhaustive",
         "results/otsu/sa/",
         "results/otsu/vns/",
+        "results/otsu/tabu/",
+        "results/otsu/hybrid/",
         "results/kapur/exhaustive",
         "results/kapur/sa/",
         "results/kapur/vns/",
+        "results/kapur/tabu/",
+        "results/kapur/hybrid/",
+        "results/otsu/pso/",
+        "results/kapur/pso/",
+        "results/otsu/ga/",
+        "results/kapur/ga/",
+        "results/otsu/de_rand/",
+        "results/kapur/de_rand/",
+        "results/otsu/de_best/",
+        "results/kapur/de_best/",
+        "results/otsu/hs/",
+        "results/kapur/hs/",
+        "results/li/exhaustive",
+        "results/li/sa/",
+        "results/li/vns/",
+        "results/li/tabu/",
+        "results/li/hybrid/",
+        "results/kittler/exhaustive",
+        "results/kittler/sa/",
+        "results/kittler/vns/",
+        "results/kittler/tabu/",
+        "results/kittler/hybrid/",
+        "results/fuzzy_trapezoid/sa/",
+        "results/fuzzy_trapezoid/vns/",
+        "results/fuzzy_s/sa/",
+        "results/fuzzy_s/vns/",
+        "results/fuzzy_type2/sa/",
+        "results/fuzzy_type2/vns/",
+        "results/otsu_2d/sa/",
+        "results/otsu_2d/vns/",
+        "results/kapur_2d/sa/",
+        "results/kapur_2d/vns/",
     ];
     for dir in dirs.iter() {
         fs::create_dir_all(dir).expect("Failed to create directory");
@@ -38,11 +125,17 @@ fn run() -> Result<(), Box<dyn std::error::Error>> {
     }
     println!("meep");
 
+    // A sequence is processed as a whole, in frame order
+    if let Command::Sequence(args) = &command {
+        process_sequence(&img_dir, args, &cli);
+        return Ok(());
+    }
+
     // Iterate over all files in the directory
     for entry in fs::read_dir(img_dir).expect("Failed to read directory") {
         let entry = entry.expect("Failed to read directory entry");
 
-        process_file(entry);
+        process_file(entry, &command, &cli);
     }
     Ok(())
 }
@@ -61,66 +154,84 @@ fn main() {
     std::io::stdin().read_line(&mut input).unwrap();
 }
 
-fn process_file(entry: fs::DirEntry) {
+fn process_file(entry: fs::DirEntry, command: &Command, cli: &Cli) {
     let img_path = entry.path();
+    // Histogram files replace the images for this command
+    if let Command::Histogram(args) = command {
+        if img_path.is_file() && img_path.extension().is_some_and(|ext| ext == "csv" || ext == "json") {
+            process_histogram_file(&img_path, args, cli);
+        }
+        return;
+    }
     // Check if the entry is a file and has an image extension
     if img_path.is_file()
         && img_path
             .extension()
             .map_or(false, |ext| ext == "jpg" || ext == "png" || ext == "jpeg")
     {
-        process_image(&img_path);
+        match command {
+            Command::Run => process_image(&img_path, cli),
+            Command::Pareto(args) => process_image_pareto(&img_path, args, cli),
+            Command::AutoK(args) => process_image_auto_k(&img_path, args, cli),
+            Command::Convergence(args) => process_image_convergence(&img_path, args, cli),
+            Command::Benchmark(args) => process_image_benchmark(&img_path, args, cli),
+            Command::Tiled(args) => process_image_tiled(&img_path, args, cli),
+            Command::Histogram(_) | Command::Sequence(_) => unreachable!(),
+        }
     }
 }
 
-fn process_image(img_path: &PathBuf) {
+fn process_image(img_path: &PathBuf, cli: &Cli) {
     // Read the image
 
-    let img = image::open(&img_path).expect("Failed to open image");
+    // Grayscale image after the configured preprocessing
+    let gray_img = load_image(img_path, cli);
 
-    // Convert the image to grayscale (if it's not already)
-    let gray_img = img.to_luma8();
+    let to_run: Vec<(&str, &str)> = vec![
+        // ("otsu", "sa"),
+        // ("kapur", "sa"),
+        // ("otsu", "vns"),
+        // ("kapur", "vns"),
+        // ("otsu", "pso"),
+        // ("kapur", "pso"),
+        // ("otsu", "ga"),
+        // ("kapur", "ga"),
+        // ("otsu", "de_rand"),
+        // ("kapur", "de_rand"),
+        // ("otsu", "de_best"),
+        // ("kapur", "de_best"),
+        // ("otsu", "hs"),
+        // ("kapur", "hs"),
+        // ("otsu", "tabu"),
+        // ("kapur", "tabu"),
+        // ("li", "tabu"),
+        // ("kittler", "tabu"),
+        // ("otsu", "hybrid"),
+        // ("kapur", "hybrid"),
+        // ("li", "hybrid"),
+        // ("kittler", "hybrid"),
+        // ("li", "sa"),
+        // ("li", "vns"),
+        // ("fuzzy_trapezoid", "sa"),
+        // ("fuzzy_trapezoid", "vns"),
+        // ("fuzzy_s", "sa"),
+        // ("fuzzy_s", "vns"),
+        // ("fuzzy_type2", "sa"),
+        // ("fuzzy_type2", "vns"),
+        ("kapur", "exhaustive"),
+        ("otsu", "exhaustive"),
+        // ("li", "exhaustive"),
+        // ("kittler", "exhaustive"),
+        // ("kittler", "sa"),
+        // ("kittler", "vns"),
+    ];
+    let metric_names: Vec<&str> = to_run.iter().map(|&(metric_name, _)| metric_name).collect();
+    let histogram = prepare_histogram(img_path, image_histogram(&gray_img, &metric_names), cli);
 
     // Histogram exploration
     let exclude_zero = true;
-    //explore_histgram(img_path, &gray_img, exclude_zero);
-
-    let to_run: Vec<(
-        &str,
-        &str,
-        fn(&str, &image::ImageBuffer<Luma<u8>, Vec<u8>>, usize) -> Vec<u8>,
-    )> = vec![
-        // (
-        //     "otsu",
-        //     "sa",
-        //     otsu::compute_otsu_thresholds_simulated_annealing,
-        // ),
-        // (
-        //     "kapur",
-        //     "sa",
-        //     kapur::compute_kapur_thresholds_simulated_annealing,
-        // ),
-        // (
-        //     "otsu",
-        //     "vns",
-        //     otsu::compute_otsu_thresholds_variable_neighborhood,
-        // ),
-        // (
-        //     "kapur",
-        //     "vns",
-        //     kapur::compute_kapur_thresholds_variable_neighborhood,
-        // ),
-        (
-            "kapur",
-            "exhaustive",
-            kapur::compute_exhaustive_kapur_thresholds,
-        ),
-        (
-            "otsu",
-            "exhaustive",
-            otsu::compute_exhaustive_otsu_thresholds,
-        ),
-    ];
+    //explore_histgram(img_path, &histogram, exclude_zero);
+
     let file_stem = img_path.file_stem().unwrap().to_string_lossy();
     println!(
         "Processing image {:?}\nWhat will be run:
@@ -137,54 +248,486 @@ fn process_image(img_path: &PathBuf) {
     std::io::stdin().read_line(&mut input).unwrap();
     for k in 2..=4 {
         for _ in 0..1 {
-            do_metric_thresholding(&to_run, img_path, &gray_img, k, exclude_zero);
+            do_metric_thresholding(&to_run, img_path, &gray_img, &histogram, k, exclude_zero, cli);
+        }
+    }
+}
+
+// Metrics whose objective can be evaluated directly on thresholds
+fn threshold_metric(metric_name: &str) -> Option<&'static Metric> {
+    THRESHOLD_METRICS
+        .iter()
+        .find(|metric| metric.name == metric_name)
+        .map(|metric| metric.metric)
+}
+
+fn find_metric(metric_name: &str) -> &'static Metric {
+    threshold_metric(metric_name).unwrap_or_else(|| panic!("Unknown or unsupported metric '{}'", metric_name))
+}
+
+fn process_image_auto_k(img_path: &Path, args: &AutoKArgs, cli: &Cli) {
+    let config = &cli.search;
+    let gray_img = load_image(img_path, cli);
+    let file_stem = img_path.file_stem().unwrap().to_string_lossy();
+    let runner = find_method(&args.metric, &args.method);
+    if args.max_k < 2 {
+        panic!("The number of classes 'k' must be at least 2.");
+    }
+
+    let histogram = prepare_histogram(img_path, image_histogram(&gray_img, &[&args.metric]), cli);
+
+    // Thresholds and criterion value for every k
+    let mut curve = vec![];
+    for k in 2..=args.max_k {
+        println!("{}: {}, k={}", args.metric, args.method, k);
+        let thresholds = runner.run(&file_stem, &histogram, k, config);
+        let thresholds_usize: Vec<usize> = thresholds.iter().map(|&t| t as usize).collect();
+        let score = args.criterion.score(&histogram, &thresholds_usize);
+        curve.push((k, thresholds, score));
+    }
+    let scores: Vec<f64> = curve.iter().map(|(_, _, score)| *score).collect();
+    let selected = args.criterion.select(&scores, args.tolerance);
+    let (best_k, best_thresholds, _) = &curve[selected];
+    println!("Selected k={} with {}: {:?}", best_k, args.criterion.name(), best_thresholds);
+
+    let base_path = format!("results/auto_k/{}/{}", args.metric, args.method);
+    fs::create_dir_all(&base_path).expect("Failed to create directory");
+    file_writing::write_auto_k_curve(
+        &format!("{}/{}_{}_curve.csv", base_path, file_stem, args.criterion.name()),
+        args.criterion.name(),
+        &curve,
+        *best_k,
+    );
+    histogram_drawer::draw_auto_k_curve(
+        &file_stem,
+        args.criterion.name(),
+        &curve.iter().map(|(k, _, score)| (*k, *score)).collect::<Vec<_>>(),
+        *best_k,
+        &format!("{}/{}_{}_curve.png", base_path, file_stem, args.criterion.name()),
+    );
+    let output = ResultOutput {
+        histogram_path: format!("{}/{}_k{}_histogram_{:?}.png", base_path, file_stem, best_k, best_thresholds),
+        segmented_path: format!("{}/{}_k{}_{:?}.png", base_path, file_stem, best_k, best_thresholds),
+        method_name: &args.metric,
+        search_name: &args.method,
+        segmentation: &cli.segmentation,
+    };
+    draw_threshold_hist_and_save_image(&output, &file_stem, &gray_img, &histogram, best_thresholds, true);
+}
+
+fn process_image_convergence(img_path: &Path, args: &ConvergenceArgs, cli: &Cli) {
+    let config = &cli.search;
+    let histogram = prepare_histogram(img_path, image_histogram(&load_image(img_path, cli), &[&args.metric]), cli);
+    let file_stem = img_path.file_stem().unwrap().to_string_lossy();
+    let runner = find_method(&args.metric, &args.method);
+    if args.method == "exhaustive" {
+        panic!("The exhaustive search has no convergence trace.");
+    }
+
+    // One traced run per seed, read back from the trace files they write
+    let mut runs = vec![];
+    for run in 0..args.runs {
+        let run_config = SearchConfig {
+            seed: config.seed + run,
+            trace: true,
+            ..config.clone()
+        };
+        println!("{}: {}, k={}, seed={}", args.metric, args.method, args.k, run_config.seed);
+        runner.run(&file_stem, &histogram, args.k, &run_config);
+        let trace = file_writing::read_trace(&search::trace_path(
+            &args.metric,
+            &args.method,
+            &file_stem,
+            args.k,
+            run_config.seed,
+        ));
+        runs.push(trace.iter().map(|point| point.best).collect::<Vec<f64>>());
+    }
+
+    let base_path = format!("results/convergence/{}/{}", args.metric, args.method);
+    fs::create_dir_all(&base_path).expect("Failed to create directory");
+    histogram_drawer::draw_convergence(
+        &file_stem,
+        &format!("{} {} k={}", args.metric, args.method, args.k),
+        &runs,
+        &format!("{}/{}_k{}_convergence.png", base_path, file_stem, args.k),
+    );
+}
+
+fn process_image_benchmark(img_path: &Path, args: &BenchmarkArgs, cli: &Cli) {
+    let config = &cli.search;
+    let histogram = prepare_histogram(img_path, Histogram::from_image(&load_image(img_path, cli)), cli);
+    let file_stem = img_path.file_stem().unwrap().to_string_lossy();
+    let intensity_levels = histogram.levels();
+
+    // Fail on an unknown pair before any run is spent
+    for metric_name in args.metrics.iter() {
+        find_metric(metric_name);
+        find_method(metric_name, "exhaustive");
+        for method in args.methods.iter() {
+            find_method(metric_name, method);
+        }
+    }
+
+    let mut sections = vec![];
+    for metric_name in args.metrics.iter() {
+        let metric = find_metric(metric_name);
+        let objective = |thresholds: &[u8]| {
+            let thresholds: Vec<usize> = thresholds.iter().map(|&t| t as usize).collect();
+            (metric.objective)(&histogram, &thresholds, intensity_levels)
+        };
+
+        for &k in args.ks.iter() {
+            // Reference optimum, from the cache when an exhaustive run already found it
+            let optimum = match optimum_cache::lookup(metric_name, &histogram, k) {
+                Some(optimum) => optimum,
+                None => {
+                    println!("{}: exhaustive, k={}", metric_name, k);
+                    objective(&find_method(metric_name, "exhaustive").run(&file_stem, &histogram, k, config))
+                }
+            };
+
+            let mut summaries = vec![];
+            let mut samples = vec![];
+            for method in args.methods.iter() {
+                let runner = find_method(metric_name, method);
+                let mut values = vec![];
+                let mut times_ms = vec![];
+                for run in 0..args.runs {
+                    let run_config = SearchConfig {
+                        seed: config.seed + run,
+                        ..config.clone()
+                    };
+                    println!("{}: {}, k={}, seed={}", metric_name, method, k, run_config.seed);
+                    let start_time = Instant::now();
+                    let thresholds = runner.run(&file_stem, &histogram, k, &run_config);
+                    times_ms.push(start_time.elapsed().as_secs_f64() * 1000.0);
+                    values.push(objective(&thresholds));
+                }
+                summaries.push(benchmark::summarise(
+                    method,
+                    metric,
+                    &values,
+                    &times_ms,
+                    optimum,
+                    args.success_tolerance,
+                ));
+                samples.push(values);
+            }
+            sections.push(benchmark::compare(metric_name, k, optimum, metric, summaries, &samples));
+        }
+    }
+
+    let base_path = "results/benchmark";
+    fs::create_dir_all(base_path).expect("Failed to create directory");
+    file_writing::write_benchmark_csv(&format!("{}/{}_benchmark.csv", base_path, file_stem), &file_stem, &sections);
+    file_writing::write_benchmark_markdown(
+        &format!("{}/{}_benchmark.md", base_path, file_stem),
+        &file_stem,
+        args.runs,
+        &sections,
+    );
+    println!("Benchmark summary saved to {}/{}_benchmark.md", base_path, file_stem);
+}
+
+// Every metric/method pair of the histogram command has to exist before the first file is run
+fn check_histogram_pairs(args: &HistogramArgs) {
+    let available = available_methods();
+    for metric_name in args.metrics.iter() {
+        // A histogram file carries no spatial information
+        if spatial::is_2d(metric_name) {
+            let message = format!("{} needs the 2D histogram of an image and cannot run on a histogram file", metric_name);
+            Cli::command().error(ErrorKind::InvalidValue, message).exit();
+        }
+        for method in args.methods.iter() {
+            if !available.iter().any(|(metric, name, _)| metric == metric_name && name == method) {
+                let methods: Vec<&str> = available
+                    .iter()
+                    .filter(|(metric, _, _)| metric == metric_name)
+                    .map(|&(_, name, _)| name)
+                    .collect();
+                let message = if methods.is_empty() {
+                    format!("unknown metric '{}'", metric_name)
+                } else {
+                    format!("{} cannot run with '{}', it supports {}", metric_name, method, methods.join(", "))
+                };
+                Cli::command().error(ErrorKind::InvalidValue, message).exit();
+            }
+        }
+    }
+}
+
+fn process_histogram_file(histogram_path: &Path, args: &HistogramArgs, cli: &Cli) {
+    let config = &cli.search;
+    // A broken file is skipped, the others still run
+    let raw = match file_writing::read_histogram(histogram_path) {
+        Ok(raw) => raw,
+        Err(error) => {
+            eprintln!("Skipping {}: {}", histogram_path.display(), error);
+            return;
         }
+    };
+    let histogram = prepare_histogram(histogram_path, raw, cli);
+    let file_stem = histogram_path.file_stem().unwrap().to_string_lossy();
+
+    let base_path = "results/histogram";
+    let mut rows = vec![];
+    for metric_name in args.metrics.iter() {
+        for method in args.methods.iter() {
+            let runner = find_method(metric_name, method);
+            for &k in args.ks.iter() {
+                println!("{}: {}, k={}", metric_name, method, k);
+                let thresholds = runner.run(&file_stem, &histogram, k, config);
+                // Fuzzy metrics are defined on membership parameters, not on the thresholds
+                let objective = threshold_metric(metric_name).map(|metric| {
+                    let thresholds: Vec<usize> = thresholds.iter().map(|&t| t as usize).collect();
+                    (metric.objective)(&histogram, &thresholds, histogram.levels())
+                });
+                if let Some(value) = objective {
+                    println!("Objective: {}", value);
+                }
+
+                let plot_path = format!("{}/{}/{}/k{}", base_path, metric_name, method, k);
+                fs::create_dir_all(&plot_path).expect("Failed to create directory");
+                histogram_drawer::draw_histogram_with_thresholds(
+                    &file_stem,
+                    metric_name,
+                    method,
+                    &histogram,
+                    &format!("{}/{}_k{}_histogram_{:?}.png", plot_path, file_stem, k, thresholds),
+                    &thresholds,
+                    false,
+                );
+                rows.push((metric_name.clone(), method.clone(), k, thresholds, objective));
+            }
+        }
+    }
+
+    file_writing::write_histogram_results(&format!("{}/{}_thresholds.csv", base_path, file_stem), &file_stem, &rows);
+    println!("Thresholds saved to {}/{}_thresholds.csv", base_path, file_stem);
+}
+
+fn process_image_tiled(img_path: &Path, args: &TiledArgs, cli: &Cli) {
+    let gray_img = load_image(img_path, cli);
+    let file_stem = img_path.file_stem().unwrap().to_string_lossy();
+    let runner = find_method(&args.metric, &args.method);
+    if args.k < 2 {
+        panic!("The number of classes 'k' must be at least 2.");
+    }
+
+    println!("{}: {}, k={}, {}x{} tiles", args.metric, args.method, args.k, args.tiles, args.tiles);
+    let grid = adaptive::tile_thresholds(&file_stem, &gray_img, args, runner, cli);
+    let maps = adaptive::threshold_maps(&grid, gray_img.width(), gray_img.height());
+
+    let base_path = format!("results/tiled/{}/{}/k{}", args.metric, args.method, args.k);
+    fs::create_dir_all(&base_path).expect("Failed to create directory");
+    let output_path = format!("{}/{}_k{}_tiles{}", base_path, file_stem, args.k, args.tiles);
+    file_writing::write_tile_thresholds(&format!("{}_thresholds.csv", output_path), &grid.tiles);
+    for (i, map) in maps.iter().enumerate() {
+        map.save(format!("{}_threshold_map{}.png", output_path, i + 1))
+            .expect("Failed to save threshold map");
+    }
+
+    // A pixel falls above every threshold of its own map that it exceeds
+    let mut labels = GrayImage::new(gray_img.width(), gray_img.height());
+    for (x, y, pixel) in gray_img.enumerate_pixels() {
+        let class = maps.iter().take_while(|map| pixel[0] > map.get_pixel(x, y)[0]).count();
+        labels.put_pixel(x, y, Luma([class as u8]));
+    }
+    let (labels, segmented_img) = color_classes(labels, args.k, &cli.segmentation);
+    save_segmentation(&format!("{}.png", output_path), &labels, &segmented_img, args.k, &cli.segmentation);
+}
+
+fn process_sequence(img_dir: &Path, args: &SequenceArgs, cli: &Cli) {
+    let config = &cli.search;
+    let frames = sequence::frame_paths(img_dir, args.pattern.as_deref());
+    if frames.is_empty() {
+        panic!("No frames found in {}", img_dir.display());
+    }
+    let runner = find_method(&args.metric, &args.method);
+    if args.k < 2 {
+        panic!("The number of classes 'k' must be at least 2.");
+    }
+
+    // Every frame starts from the thresholds found on the frame before it
+    let mut images = vec![];
+    let mut names = vec![];
+    let mut raw = vec![];
+    let mut previous: Option<Vec<usize>> = None;
+    for (index, frame_path) in frames.iter().enumerate() {
+        let gray_img = load_image(frame_path, cli);
+        let histogram = prepare_histogram(frame_path, image_histogram(&gray_img, &[&args.metric]), cli);
+        let file_stem = frame_path.file_stem().unwrap().to_string_lossy().to_string();
+        let frame_config = SearchConfig {
+            initial_thresholds: if args.no_warm_start {
+                config.initial_thresholds.clone()
+            } else {
+                previous.clone().or_else(|| config.initial_thresholds.clone())
+            },
+            ..config.clone()
+        };
+        println!("Frame {} ({}): {}: {}, k={}", index, file_stem, args.metric, args.method, args.k);
+        let thresholds = runner.run(&file_stem, &histogram, args.k, &frame_config);
+        previous = Some(thresholds.iter().map(|&t| t as usize).collect());
+        images.push(gray_img);
+        names.push(file_stem);
+        raw.push(thresholds);
+    }
+    let smoothed = sequence::smooth(&raw, args.temporal_smoothing, args.temporal_sigma, args.temporal_radius);
+
+    let base_path = format!("results/sequence/{}/{}/k{}", args.metric, args.method, args.k);
+    fs::create_dir_all(&base_path).expect("Failed to create directory");
+    file_writing::write_sequence_thresholds(&format!("{}/thresholds.csv", base_path), &names, &raw, &smoothed);
+    histogram_drawer::draw_thresholds_over_time(
+        &format!("{} {} k={}", args.metric, args.method, args.k),
+        &raw,
+        (args.temporal_smoothing != histogram_processing::Smoothing::None).then_some(&smoothed[..]),
+        &format!("{}/thresholds_over_time.png", base_path),
+    );
+
+    // Frames are segmented with the smoothed thresholds
+    for ((gray_img, file_stem), thresholds) in images.iter().zip(names.iter()).zip(smoothed.iter()) {
+        let (labels, segmented_img) = apply_thresholds(gray_img, thresholds, &cli.segmentation);
+        save_segmentation(
+            &format!("{}/{}_k{}_{:?}.png", base_path, file_stem, args.k, thresholds),
+            &labels,
+            &segmented_img,
+            args.k,
+            &cli.segmentation,
+        );
+    }
+}
+
+fn process_image_pareto(img_path: &Path, args: &ParetoArgs, cli: &Cli) {
+    let config = &cli.search;
+    let histogram = prepare_histogram(img_path, Histogram::from_image(&load_image(img_path, cli)), cli);
+    let file_stem = img_path.file_stem().unwrap().to_string_lossy();
+    let k = args.k;
+    if k < 2 {
+        panic!("The number of classes 'k' must be at least 2.");
+    }
+
+    println!("otsu+kapur: nsga2");
+    let mut rng = StdRng::seed_from_u64(config.seed);
+    let space = SearchSpace::thresholds(k);
+    let initial = search::starting_point(config, &file_stem, &histogram, &space, &mut rng);
+    let front = pareto::nsga2(
+        &mut rng,
+        &histogram,
+        &space,
+        initial,
+        &[&otsu::OTSU, &kapur::KAPUR],
+        config,
+    );
+    println!("{} thresholds on the Pareto front", front.len());
+
+    let base_path = format!("results/pareto/k{}", k);
+    fs::create_dir_all(&base_path).expect("Failed to create directory");
+    file_writing::write_pareto_front(
+        &format!("{}/{}_k{}_pareto.csv", base_path, file_stem, k),
+        &["otsu", "kapur"],
+        &front,
+    );
+    let points: Vec<(f64, f64)> = front
+        .iter()
+        .map(|solution| (solution.objectives[0], solution.objectives[1]))
+        .collect();
+    histogram_drawer::draw_pareto_front(
+        &file_stem,
+        k,
+        "Between-class variance",
+        "Total entropy",
+        &points,
+        &format!("{}/{}_k{}_pareto.png", base_path, file_stem, k),
+    );
+}
+
+// Grey-level image after the configured preprocessing chain, recorded in the run metadata
+fn load_image(img_path: &Path, cli: &Cli) -> GrayImage {
+    let img = image::open(img_path).expect("Failed to open image");
+    let gray_img = image_processing::apply_chain(img.to_luma8(), &cli.preprocess);
+    file_writing::write_run_metadata(
+        "results/run_metadata.csv",
+        &img_path.file_stem().unwrap().to_string_lossy(),
+        gray_img.width(),
+        gray_img.height(),
+        &image_processing::describe(&cli.preprocess),
+        &cli.histogram.describe(),
+        cli.search.seed,
+    );
+    gray_img
+}
+
+// Histogram of the image, the joint histogram is only built when a 2D metric will read it
+fn image_histogram(gray_img: &GrayImage, metric_names: &[&str]) -> Histogram {
+    let histogram = Histogram::from_image(gray_img);
+    if metric_names.iter().any(|metric_name| spatial::is_2d(metric_name)) {
+        histogram.with_joint(gray_img)
+    } else {
+        histogram
     }
 }
 
-pub fn explore_histgram(img_path: &PathBuf, gray_img: &GrayImage, exclude_zero: bool) {
+// Histogram the searchers run on, preprocessed as configured. When preprocessing changes
+// it, the raw and processed histograms are drawn together in the histograms folder
+fn prepare_histogram(img_path: &Path, raw: Histogram, cli: &Cli) -> Histogram {
+    if cli.histogram.is_identity() {
+        return raw;
+    }
+    let processed = histogram_processing::preprocess(&raw, &cli.histogram);
+    let file_stem = img_path.file_stem().unwrap().to_string_lossy();
+    fs::create_dir_all("histograms").expect("Failed to create directory");
+    histogram_drawer::save_histogram_comparison(
+        &file_stem,
+        &raw,
+        &processed,
+        &format!("histograms/{}_preprocessed_histogram.png", file_stem),
+        false,
+    );
+    processed
+}
+
+pub fn explore_histgram(img_path: &PathBuf, histogram: &Histogram, exclude_zero: bool) {
     // Read the image
     let file_stem = img_path.file_stem().unwrap().to_string_lossy();
     let output_path = format!("histograms/{}_histogram.png", file_stem);
-    histogram_drawer::save_histogram(&file_stem, &gray_img, &output_path, exclude_zero);
+    histogram_drawer::save_histogram(&file_stem, histogram, &output_path, exclude_zero);
 }
 
 pub fn do_metric_thresholding(
-    to_run: &Vec<(
-        &str,
-        &str,
-        fn(image_name: &str, gray_img: &GrayImage, k: usize) -> Vec<u8>,
-    )>,
+    to_run: &[(&str, &str)],
     img_path: &PathBuf,
     gray_img: &GrayImage,
+    histogram: &Histogram,
     k: usize,
     exclude_zero: bool,
+    cli: &Cli,
 ) {
     let file_stem = img_path.file_stem().unwrap().to_string_lossy();
-    for func in to_run.iter() {
+    for &pair in to_run.iter() {
         do_metric_method(
-            func.0,
-            func.1,
-            func.2,
+            pair,
             &file_stem,
             gray_img,
+            histogram,
             k,
             exclude_zero,
+            cli,
         );
     }
 }
 
 pub fn do_metric_method(
-    metric_name: &str,
-    method_name: &str,
-    compute_thresholds_fn: fn(&str, &GrayImage, usize) -> Vec<u8>,
+    (metric_name, method_name): (&str, &str),
     file_stem: &str,
     gray_img: &GrayImage,
+    histogram: &Histogram,
     k: usize,
     exclude_zero: bool,
+    cli: &Cli,
 ) {
     println!("{}: {}", metric_name, method_name.replace('_', " "));
-    let thresholds = compute_thresholds_fn(&file_stem, gray_img, k);
+    let thresholds = find_method(metric_name, method_name).run(file_stem, histogram, k, &cli.search);
     let base_path = format!(
         "results/{}/{}/k{}",
         metric_name,
@@ -196,57 +739,81 @@ pub fn do_metric_method(
         base_path, file_stem, k, thresholds
     );
     let segmented_path = format!("{}/{}_k{}_{:?}.png", base_path, file_stem, k, thresholds);
-    draw_threshold_hist_and_save_image(
-        &histogram_path,
-        &segmented_path,
-        file_stem,
-        gray_img,
-        &thresholds,
-        exclude_zero,
-        metric_name,
-        method_name,
-    );
+    let output = ResultOutput {
+        histogram_path,
+        segmented_path,
+        method_name: metric_name,
+        search_name: method_name,
+        segmentation: &cli.segmentation,
+    };
+    draw_threshold_hist_and_save_image(&output, file_stem, gray_img, histogram, &thresholds, exclude_zero);
+}
+
+// Where one thresholding result is saved, under which names, and how its class map is cleaned up
+struct ResultOutput<'a> {
+    histogram_path: String,
+    segmented_path: String,
+    method_name: &'a str,
+    search_name: &'a str,
+    segmentation: &'a SegmentationConfig,
 }
 
 fn draw_threshold_hist_and_save_image(
-    histogram_path: &str,
-    segmented_path: &str,
+    output: &ResultOutput,
     file_stem: &str,
     gray_img: &GrayImage,
+    histogram: &Histogram,
     thresholds: &Vec<u8>,
     exclude_zero: bool,
-    method_name: &str,
-    search_name: &str,
 ) {
     histogram_drawer::draw_histogram_with_thresholds(
         file_stem,
-        method_name,
-        search_name,
-        gray_img,
-        histogram_path,
+        output.method_name,
+        output.search_name,
+        histogram,
+        &output.histogram_path,
         thresholds,
         exclude_zero,
     );
-    let segmented_img = apply_thresholds(gray_img, thresholds);
+    let (labels, segmented_img) = apply_thresholds(gray_img, thresholds, output.segmentation);
+    save_segmentation(&output.segmented_path, &labels, &segmented_img, thresholds.len() + 1, output.segmentation);
+}
+
+// Saves the colored image, then the class map, masks and region statistics next to it
+fn save_segmentation(
+    segmented_path: &str,
+    labels: &GrayImage,
+    segmented_img: &image::RgbImage,
+    classes: usize,
+    config: &SegmentationConfig,
+) {
     segmented_img
         .save(segmented_path)
         .expect("Failed to save image");
+
+    let output_path = segmented_path.trim_end_matches(".png");
+    if let Some(extension) = config.label_map.extension() {
+        labels
+            .save(format!("{}_labels.{}", output_path, extension))
+            .expect("Failed to save label map");
+    }
+    if config.class_masks {
+        for class in 0..classes {
+            morphology::class_mask_image(labels, class as u8)
+                .save(format!("{}_class{}_mask.png", output_path, class))
+                .expect("Failed to save class mask");
+        }
+    }
+    let regions = morphology::connected_regions(labels, config.connectivity);
+    file_writing::write_regions(&format!("{}_regions.csv", output_path), &regions);
+    file_writing::write_class_regions(&format!("{}_classes.csv", output_path), &regions, classes);
 }
 
-fn apply_thresholds(gray_img: &GrayImage, thresholds: &[u8]) -> image::RgbImage {
-    let mut segmented_img = image::RgbImage::new(gray_img.width(), gray_img.height());
+// Class map of the image and its colored version, the class map cleaned up as configured
+fn apply_thresholds(gray_img: &GrayImage, thresholds: &[u8], config: &SegmentationConfig) -> (GrayImage, image::RgbImage) {
+    let mut labels = GrayImage::new(gray_img.width(), gray_img.height());
     let k = thresholds.len() + 1; // Number of classes
 
-    // Define a set of colors for the segments
-    let colors = [
-        image::Rgb([0, 0, 255]),   // Blue
-        image::Rgb([0, 255, 0]),   // Green
-        image::Rgb([255, 0, 0]),   // Red
-        image::Rgb([255, 255, 0]), // Yellow
-        image::Rgb([255, 0, 255]), // Magenta
-        image::Rgb([0, 255, 255]), // Cyan
-    ];
-
     for (x, y, pixel) in gray_img.enumerate_pixels() {
         let intensity = pixel[0];
         let mut class = 0usize;
@@ -257,9 +824,31 @@ fn apply_thresholds(gray_img: &GrayImage, thresholds: &[u8]) -> image::RgbImage
                 break;
             }
         }
+        labels.put_pixel(x, y, Luma([class as u8]));
+    }
+    color_classes(labels, k, config)
+}
+
+// Cleans up the class map as configured and colors every class
+fn color_classes(mut labels: GrayImage, k: usize, config: &SegmentationConfig) -> (GrayImage, image::RgbImage) {
+    let mut segmented_img = image::RgbImage::new(labels.width(), labels.height());
+
+    // Define a set of colors for the segments
+    let colors = [
+        image::Rgb([0, 0, 255]),   // Blue
+        image::Rgb([0, 255, 0]),   // Green
+        image::Rgb([255, 0, 0]),   // Red
+        image::Rgb([255, 255, 0]), // Yellow
+        image::Rgb([255, 0, 255]), // Magenta
+        image::Rgb([0, 255, 255]), // Cyan
+    ];
+
+    morphology::clean(&mut labels, k, config);
+
+    for (x, y, class) in labels.enumerate_pixels() {
         // Assign the color based on the class
-        let color = colors[class % colors.len()];
+        let color = colors[class[0] as usize % colors.len()];
         segmented_img.put_pixel(x, y, color);
     }
-    segmented_img
+    (labels, segmented_img)
 }
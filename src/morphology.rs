@@ -0,0 +1,283 @@
+use std::collections::HashMap;
+
+use clap::{Args, ValueEnum};
+use image::{GrayImage, Luma};
+
+// Pixels that touch to form one region
+#[derive(Clone, Copy, Debug, PartialEq, ValueEnum)]
+pub enum Connectivity {
+    /// Edge neighbours only
+    Four,
+    /// Edge and corner neighbours
+    Eight,
+}
+
+// File format of the saved class map
+#[derive(Clone, Copy, Debug, PartialEq, ValueEnum)]
+pub enum LabelFormat {
+    /// No class map
+    None,
+    /// Single-channel 8-bit PNG
+    Png,
+    /// Single-channel 8-bit TIFF
+    Tiff,
+}
+
+impl LabelFormat {
+    pub fn extension(self) -> Option<&'static str> {
+        match self {
+            LabelFormat::None => None,
+            LabelFormat::Png => Some("png"),
+            LabelFormat::Tiff => Some("tiff"),
+        }
+    }
+}
+
+// Cleanup of the class map produced by the thresholds, applied as opening, then closing
+// of every class in turn, then small-region removal
+#[derive(Args, Clone)]
+pub struct SegmentationConfig {
+    /// Segmentation: radius of the square opening of every class mask, 0 to skip
+    #[arg(long, global = true, default_value_t = 0)]
+    pub opening: u32,
+    /// Segmentation: radius of the square closing of every class mask, 0 to skip
+    #[arg(long, global = true, default_value_t = 0)]
+    pub closing: u32,
+    /// Segmentation: regions with fewer pixels are merged into the class bordering them most, 0 to keep all
+    #[arg(long, global = true, default_value_t = 0)]
+    pub min_region_size: usize,
+    /// Segmentation: pixel connectivity of the regions
+    #[arg(long, global = true, value_enum, default_value_t = Connectivity::Eight)]
+    pub connectivity: Connectivity,
+    /// Segmentation: format of the class map saved next to the colored image, pixel values are class indices
+    #[arg(long, global = true, value_enum, default_value_t = LabelFormat::Png)]
+    pub label_map: LabelFormat,
+    /// Segmentation: also save one binary mask per class, 255 inside the class
+    #[arg(long, global = true)]
+    pub class_masks: bool,
+}
+
+// One connected region of a class, the bounding box is inclusive
+pub struct Region {
+    pub class: u8,
+    pub area: usize,
+    pub centroid_x: f64,
+    pub centroid_y: f64,
+    pub min_x: u32,
+    pub min_y: u32,
+    pub max_x: u32,
+    pub max_y: u32,
+}
+
+pub fn clean(labels: &mut GrayImage, classes: usize, config: &SegmentationConfig) {
+    for class in 0..classes as u8 {
+        if config.opening > 0 {
+            open_class(labels, class, config.opening);
+        }
+        if config.closing > 0 {
+            close_class(labels, class, config.closing);
+        }
+    }
+    if config.min_region_size > 0 {
+        remove_small_regions(labels, config.min_region_size, config.connectivity);
+    }
+}
+
+fn class_mask(labels: &GrayImage, class: u8) -> Vec<bool> {
+    labels.pixels().map(|pixel| pixel[0] == class).collect()
+}
+
+// Square max (dilation) or min (erosion) filter, done as a row pass then a column pass.
+// The window is clamped at the borders, so erosion does not eat into the image edges
+fn square_filter(mask: &[bool], width: usize, height: usize, radius: usize, dilate: bool) -> Vec<bool> {
+    let pass = |input: &[bool], horizontal: bool| -> Vec<bool> {
+        let mut output = vec![false; input.len()];
+        for y in 0..height {
+            for x in 0..width {
+                let (position, size) = if horizontal { (x, width) } else { (y, height) };
+                let window = position.saturating_sub(radius)..=(position + radius).min(size - 1);
+                let mut values = window.map(|p| if horizontal { input[y * width + p] } else { input[p * width + x] });
+                output[y * width + x] = if dilate { values.any(|v| v) } else { values.all(|v| v) };
+            }
+        }
+        output
+    };
+    pass(&pass(mask, true), false)
+}
+
+// Most frequent class other than `excluded` within the window around a pixel
+fn majority_neighbour(labels: &GrayImage, x: u32, y: u32, radius: u32, excluded: u8) -> Option<u8> {
+    let mut counts = [0usize; 256];
+    for ny in y.saturating_sub(radius)..=(y + radius).min(labels.height() - 1) {
+        for nx in x.saturating_sub(radius)..=(x + radius).min(labels.width() - 1) {
+            counts[labels.get_pixel(nx, ny)[0] as usize] += 1;
+        }
+    }
+    counts[excluded as usize] = 0;
+    let (class, &count) = counts.iter().enumerate().max_by_key(|(_, &count)| count)?;
+    (count > 0).then_some(class as u8)
+}
+
+// Pixels the opening removes from the class go to the class most present around them
+fn open_class(labels: &mut GrayImage, class: u8, radius: u32) {
+    let (width, height) = (labels.width() as usize, labels.height() as usize);
+    let mask = class_mask(labels, class);
+    let eroded = square_filter(&mask, width, height, radius as usize, false);
+    let opened = square_filter(&eroded, width, height, radius as usize, true);
+
+    let reassigned: Vec<(u32, u32, u8)> = (0..mask.len())
+        .filter(|&i| mask[i] && !opened[i])
+        .filter_map(|i| {
+            let (x, y) = ((i % width) as u32, (i / width) as u32);
+            majority_neighbour(labels, x, y, radius + 1, class).map(|other| (x, y, other))
+        })
+        .collect();
+    for (x, y, other) in reassigned {
+        labels.put_pixel(x, y, Luma([other]));
+    }
+}
+
+// Pixels the closing adds to the class are taken from the classes they belonged to
+fn close_class(labels: &mut GrayImage, class: u8, radius: u32) {
+    let (width, height) = (labels.width() as usize, labels.height() as usize);
+    let mask = class_mask(labels, class);
+    let dilated = square_filter(&mask, width, height, radius as usize, true);
+    let closed = square_filter(&dilated, width, height, radius as usize, false);
+    for i in (0..mask.len()).filter(|&i| closed[i] && !mask[i]) {
+        labels.put_pixel((i % width) as u32, (i / width) as u32, Luma([class]));
+    }
+}
+
+fn neighbour_offsets(connectivity: Connectivity) -> &'static [(i64, i64)] {
+    match connectivity {
+        Connectivity::Four => &[(1, 0), (-1, 0), (0, 1), (0, -1)],
+        Connectivity::Eight => &[(1, 0), (-1, 0), (0, 1), (0, -1), (1, 1), (1, -1), (-1, 1), (-1, -1)],
+    }
+}
+
+// Labels every connected region of equal class, returns the region index of every
+// pixel in row-major order and the pixels of every region
+fn label_components(labels: &GrayImage, connectivity: Connectivity) -> (Vec<usize>, Vec<Vec<(u32, u32)>>) {
+    let (width, height) = labels.dimensions();
+    let mut component = vec![usize::MAX; (width * height) as usize];
+    let mut members = vec![];
+    for start in 0..component.len() {
+        if component[start] != usize::MAX {
+            continue;
+        }
+        let index = members.len();
+        let class = labels.as_raw()[start];
+        let mut pixels = vec![];
+        let mut stack = vec![start];
+        component[start] = index;
+        while let Some(i) = stack.pop() {
+            let (x, y) = ((i as u32 % width), (i as u32 / width));
+            pixels.push((x, y));
+            for &(dx, dy) in neighbour_offsets(connectivity) {
+                let (nx, ny) = (x as i64 + dx, y as i64 + dy);
+                if nx < 0 || ny < 0 || nx >= width as i64 || ny >= height as i64 {
+                    continue;
+                }
+                let j = (ny * width as i64 + nx) as usize;
+                if component[j] == usize::MAX && labels.as_raw()[j] == class {
+                    component[j] = index;
+                    stack.push(j);
+                }
+            }
+        }
+        members.push(pixels);
+    }
+    (component, members)
+}
+
+// Regions below the minimum size take the class found most often just outside them
+fn remove_small_regions(labels: &mut GrayImage, min_size: usize, connectivity: Connectivity) {
+    let (width, height) = labels.dimensions();
+    let (component, members) = label_components(labels, connectivity);
+    for (index, pixels) in members.iter().enumerate() {
+        if pixels.len() >= min_size {
+            continue;
+        }
+        let mut border: HashMap<u8, usize> = HashMap::new();
+        for &(x, y) in pixels {
+            for &(dx, dy) in neighbour_offsets(connectivity) {
+                let (nx, ny) = (x as i64 + dx, y as i64 + dy);
+                if nx < 0 || ny < 0 || nx >= width as i64 || ny >= height as i64 {
+                    continue;
+                }
+                if component[(ny * width as i64 + nx) as usize] != index {
+                    *border.entry(labels.get_pixel(nx as u32, ny as u32)[0]).or_insert(0) += 1;
+                }
+            }
+        }
+        // Ties go to the lower class so that the result does not depend on hashing
+        if let Some((&class, _)) = border.iter().max_by_key(|(&class, &count)| (count, std::cmp::Reverse(class))) {
+            for &(x, y) in pixels {
+                labels.put_pixel(x, y, Luma([class]));
+            }
+        }
+    }
+}
+
+pub fn connected_regions(labels: &GrayImage, connectivity: Connectivity) -> Vec<Region> {
+    let (_, members) = label_components(labels, connectivity);
+    members
+        .iter()
+        .map(|pixels| {
+            let (x, y) = pixels[0];
+            let area = pixels.len();
+            Region {
+                class: labels.get_pixel(x, y)[0],
+                area,
+                centroid_x: pixels.iter().map(|&(x, _)| x as f64).sum::<f64>() / area as f64,
+                centroid_y: pixels.iter().map(|&(_, y)| y as f64).sum::<f64>() / area as f64,
+                min_x: pixels.iter().map(|&(x, _)| x).min().unwrap(),
+                min_y: pixels.iter().map(|&(_, y)| y).min().unwrap(),
+                max_x: pixels.iter().map(|&(x, _)| x).max().unwrap(),
+                max_y: pixels.iter().map(|&(_, y)| y).max().unwrap(),
+            }
+        })
+        .collect()
+}
+
+// Binary mask of one class, 255 inside it and 0 elsewhere
+pub fn class_mask_image(labels: &GrayImage, class: u8) -> GrayImage {
+    GrayImage::from_fn(labels.width(), labels.height(), |x, y| {
+        Luma([if labels.get_pixel(x, y)[0] == class { 255 } else { 0 }])
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Two diagonal class 1 blobs on a class 0 background
+    fn tiny_mask() -> GrayImage {
+        let pixels = vec![
+            1, 1, 0, 0, 0,
+            0, 1, 0, 1, 0,
+            0, 0, 1, 1, 0,
+        ];
+        GrayImage::from_raw(5, 3, pixels).unwrap()
+    }
+
+    #[test]
+    fn components_split_on_corners_with_four_connectivity() {
+        let regions = connected_regions(&tiny_mask(), Connectivity::Four);
+        let summary: Vec<(u8, usize)> = regions.iter().map(|region| (region.class, region.area)).collect();
+        assert_eq!(summary, vec![(1, 3), (0, 6), (0, 3), (1, 3)]);
+
+        let first = &regions[0];
+        assert_eq!((first.min_x, first.min_y, first.max_x, first.max_y), (0, 0, 1, 1));
+        assert!((first.centroid_x - 2.0 / 3.0).abs() < 1e-12);
+        assert!((first.centroid_y - 1.0 / 3.0).abs() < 1e-12);
+    }
+
+    #[test]
+    fn components_join_across_corners_with_eight_connectivity() {
+        let regions = connected_regions(&tiny_mask(), Connectivity::Eight);
+        let summary: Vec<(u8, usize)> = regions.iter().map(|region| (region.class, region.area)).collect();
+        assert_eq!(summary, vec![(1, 6), (0, 9)]);
+        assert_eq!((regions[0].max_x, regions[0].max_y), (3, 2));
+    }
+}
@@ -0,0 +1,107 @@
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::Path;
+
+use crate::search::Metric;
+use crate::stats::Histogram;
+
+// Exact optima found so far, one row per (histogram, metric, k)
+const CACHE_PATH: &str = "results/optimum_cache.csv";
+// Optimality gap of every metaheuristic run whose optimum is cached
+const GAPS_PATH: &str = "results/optimality_gaps.csv";
+
+// A run hits the optimum when its gap is below this, relative to the optimum once it is above one
+const HIT_TOLERANCE: f64 = 1e-9;
+
+// FNV-1a over the bits of the normalised histogram, identical images give identical keys
+pub fn histogram_hash(histogram: &Histogram) -> u64 {
+    let mut hash: u64 = 0xcbf29ce484222325;
+    for p in &histogram.prob {
+        for byte in p.to_bits().to_le_bytes() {
+            hash ^= byte as u64;
+            hash = hash.wrapping_mul(0x100000001b3);
+        }
+    }
+    hash
+}
+
+fn append_row(file_path: &str, header: &str, row: String) {
+    let exists = Path::new(file_path).exists();
+    if let Some(parent) = Path::new(file_path).parent() {
+        fs::create_dir_all(parent).unwrap();
+    }
+    let mut file = OpenOptions::new()
+        .append(true)
+        .create(true)
+        .open(file_path)
+        .unwrap();
+    if !exists {
+        writeln!(file, "{}", header).unwrap();
+    }
+    writeln!(file, "{}", row).unwrap();
+}
+
+// Cached optimum of the metric for this histogram and k
+pub fn lookup(metric_name: &str, histogram: &Histogram, k: usize) -> Option<f64> {
+    let key = format!("{:016x}", histogram_hash(histogram));
+    let contents = fs::read_to_string(CACHE_PATH).ok()?;
+    contents.lines().skip(1).find_map(|line| {
+        let fields: Vec<&str> = line.split(',').collect();
+        if fields.len() >= 4 && fields[0] == key && fields[1] == metric_name && fields[2] == k.to_string() {
+            fields[3].parse().ok()
+        } else {
+            None
+        }
+    })
+}
+
+// Store the result of an exact search, once per key
+pub fn record_optimum(metric_name: &str, histogram: &Histogram, k: usize, thresholds: &[usize], value: f64) {
+    if lookup(metric_name, histogram, k).is_some() {
+        return;
+    }
+    append_row(
+        CACHE_PATH,
+        "histogram,metric,k,objective,thresholds",
+        format!(
+            "{:016x},{},{},{},[{}]",
+            histogram_hash(histogram),
+            metric_name,
+            k,
+            value,
+            thresholds.iter().map(|x| x.to_string()).collect::<Vec<_>>().join(";"),
+        ),
+    );
+}
+
+// Compare a metaheuristic result with the cached optimum and log the gap, if the optimum is known
+pub fn record_gap(
+    metric_name: &str,
+    metric: &Metric,
+    method: &str,
+    image_name: &str,
+    histogram: &Histogram,
+    k: usize,
+    value: f64,
+) {
+    let Some(optimum) = lookup(metric_name, histogram, k) else {
+        return;
+    };
+    let absolute_gap = metric.direction.gain(optimum, value).max(0.0);
+    let relative_gap = if optimum != 0.0 { absolute_gap / optimum.abs() } else { absolute_gap };
+    let hit = absolute_gap <= HIT_TOLERANCE * optimum.abs().max(1.0);
+    println!(
+        "Optimality gap: {} ({:.4}%), optimum {}",
+        absolute_gap,
+        relative_gap * 100.0,
+        if hit { "hit" } else { "missed" }
+    );
+    append_row(
+        GAPS_PATH,
+        "image,metric,method,k,objective,optimum,absolute_gap,relative_gap,hit",
+        format!(
+            "{},{},{},{},{},{},{},{},{}",
+            image_name, metric_name, method, k, value, optimum, absolute_gap, relative_gap, hit
+        ),
+    );
+}
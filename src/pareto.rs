@@ -0,0 +1,213 @@
+use std::cmp::Ordering;
+
+use rand::prelude::*;
+
+use crate::genetic;
+use crate::search::{self, Evaluator, Metric, SearchConfig, SearchSpace};
+use crate::stats::Histogram;
+
+// A threshold vector and its value under every metric being optimised
+#[derive(Clone)]
+pub struct Solution {
+    pub thresholds: Vec<usize>,
+    pub objectives: Vec<f64>,
+}
+
+// Every metric has its own evaluator, so a solution counts once against each of their budgets
+fn evaluate(evaluators: &mut [Evaluator], thresholds: Vec<usize>) -> Solution {
+    let objectives = evaluators
+        .iter_mut()
+        .map(|evaluator| evaluator.evaluate(&thresholds))
+        .collect();
+    Solution {
+        thresholds,
+        objectives,
+    }
+}
+
+// True if `a` is at least as good as `b` under every metric and better under one
+fn dominates(a: &Solution, b: &Solution, metrics: &[&Metric]) -> bool {
+    let mut strictly_better = false;
+    for (m, metric) in metrics.iter().enumerate() {
+        if metric.direction.improves(b.objectives[m], a.objectives[m]) {
+            return false;
+        }
+        if metric.direction.improves(a.objectives[m], b.objectives[m]) {
+            strictly_better = true;
+        }
+    }
+    strictly_better
+}
+
+// Fast non-dominated sort, returns the indices of every front starting with the Pareto front
+fn non_dominated_fronts(population: &[Solution], metrics: &[&Metric]) -> Vec<Vec<usize>> {
+    let n = population.len();
+    let mut dominated_by: Vec<Vec<usize>> = vec![vec![]; n];
+    let mut domination_count = vec![0usize; n];
+    let mut fronts: Vec<Vec<usize>> = vec![vec![]];
+
+    for p in 0..n {
+        for q in 0..n {
+            if dominates(&population[p], &population[q], metrics) {
+                dominated_by[p].push(q);
+            } else if dominates(&population[q], &population[p], metrics) {
+                domination_count[p] += 1;
+            }
+        }
+        if domination_count[p] == 0 {
+            fronts[0].push(p);
+        }
+    }
+
+    let mut i = 0;
+    while !fronts[i].is_empty() {
+        let mut next_front = vec![];
+        for &p in fronts[i].iter() {
+            for &q in dominated_by[p].iter() {
+                domination_count[q] -= 1;
+                if domination_count[q] == 0 {
+                    next_front.push(q);
+                }
+            }
+        }
+        i += 1;
+        fronts.push(next_front);
+    }
+    fronts.pop();
+    fronts
+}
+
+// Crowding distance of every member of a front, boundary points get infinity
+fn crowding_distances(population: &[Solution], front: &[usize], metrics: &[&Metric]) -> Vec<f64> {
+    let mut distances = vec![0.0; front.len()];
+    for m in 0..metrics.len() {
+        let mut order: Vec<usize> = (0..front.len()).collect();
+        order.sort_by(|&a, &b| {
+            population[front[a]].objectives[m]
+                .partial_cmp(&population[front[b]].objectives[m])
+                .unwrap_or(Ordering::Equal)
+        });
+        let min = population[front[order[0]]].objectives[m];
+        let max = population[front[order[order.len() - 1]]].objectives[m];
+        distances[order[0]] = f64::INFINITY;
+        distances[order[order.len() - 1]] = f64::INFINITY;
+        if max - min <= 0.0 {
+            continue;
+        }
+        for w in 1..order.len().saturating_sub(1) {
+            let previous = population[front[order[w - 1]]].objectives[m];
+            let next = population[front[order[w + 1]]].objectives[m];
+            distances[order[w]] += (next - previous) / (max - min);
+        }
+    }
+    distances
+}
+
+// Rank of every member (front index) and its crowding distance
+fn rank_population(population: &[Solution], metrics: &[&Metric]) -> (Vec<usize>, Vec<f64>) {
+    let mut rank = vec![0; population.len()];
+    let mut crowding = vec![0.0; population.len()];
+    for (r, front) in non_dominated_fronts(population, metrics).iter().enumerate() {
+        let distances = crowding_distances(population, front, metrics);
+        for (w, &p) in front.iter().enumerate() {
+            rank[p] = r;
+            crowding[p] = distances[w];
+        }
+    }
+    (rank, crowding)
+}
+
+// Binary tournament on rank, then crowding distance
+fn tournament(rng: &mut StdRng, rank: &[usize], crowding: &[f64]) -> usize {
+    let a = rng.gen_range(0..rank.len());
+    let b = rng.gen_range(0..rank.len());
+    if rank[a] < rank[b] || (rank[a] == rank[b] && crowding[a] > crowding[b]) {
+        a
+    } else {
+        b
+    }
+}
+
+// NSGA-II over threshold vectors, returns the final Pareto front sorted on the first metric
+pub fn nsga2(
+    rng: &mut StdRng,
+    histogram: &Histogram,
+    space: &SearchSpace,
+    initial: Option<Vec<usize>>,
+    metrics: &[&Metric],
+    config: &SearchConfig,
+) -> Vec<Solution> {
+    let population_size = config.population;
+    let generations = config.generations;
+    let mutation_rate = config.mutation_rate;
+    let mut evaluators: Vec<Evaluator> = metrics
+        .iter()
+        .map(|&metric| Evaluator::new(histogram, metric, config))
+        .collect();
+    let exhausted = |evaluators: &[Evaluator]| evaluators.iter().any(|evaluator| evaluator.exhausted());
+
+    let mut population: Vec<Solution> = (0..population_size)
+        .map(|_| evaluate(&mut evaluators, space.random(rng)))
+        .collect();
+    if let Some(initial) = initial {
+        population[0] = evaluate(&mut evaluators, initial);
+    }
+
+    let pb = search::progress_bar(generations as u64);
+
+    for _ in 0..generations {
+        if exhausted(&evaluators) {
+            break;
+        }
+        pb.inc(1);
+        let (rank, crowding) = rank_population(&population, metrics);
+
+        // Offspring with the genetic algorithm's crossover and mutation
+        let mut offspring = Vec::with_capacity(population_size);
+        while offspring.len() < population_size {
+            // Out of budget mid-generation: survival runs on the offspring made so far
+            if exhausted(&evaluators) {
+                break;
+            }
+            let a = &population[tournament(rng, &rank, &crowding)].thresholds;
+            let b = &population[tournament(rng, &rank, &crowding)].thresholds;
+            let mut child = genetic::order_preserving_crossover(rng, space, a, b);
+            if rng.gen_bool(mutation_rate) {
+                child = genetic::mutate(rng, space, &child);
+            }
+            offspring.push(evaluate(&mut evaluators, child));
+        }
+
+        // Elitist survival over parents and offspring
+        population.extend(offspring);
+        let mut next = Vec::with_capacity(population_size);
+        for front in non_dominated_fronts(&population, metrics) {
+            if next.len() + front.len() <= population_size {
+                next.extend(front.iter().map(|&p| population[p].clone()));
+            } else {
+                let distances = crowding_distances(&population, &front, metrics);
+                let mut order: Vec<usize> = (0..front.len()).collect();
+                order.sort_by(|&a, &b| distances[b].partial_cmp(&distances[a]).unwrap_or(Ordering::Equal));
+                for &w in order.iter().take(population_size - next.len()) {
+                    next.push(population[front[w]].clone());
+                }
+                break;
+            }
+        }
+        population = next;
+    }
+    pb.finish_with_message(format!("Done after {} evaluations", evaluators[0].evaluations));
+
+    let mut front: Vec<Solution> = non_dominated_fronts(&population, metrics)[0]
+        .iter()
+        .map(|&p| population[p].clone())
+        .collect();
+    front.sort_by(|a, b| {
+        a.objectives[0]
+            .partial_cmp(&b.objectives[0])
+            .unwrap_or(Ordering::Equal)
+            .then_with(|| a.thresholds.cmp(&b.thresholds))
+    });
+    front.dedup_by(|a, b| a.thresholds == b.thresholds);
+    front
+}
@@ -0,0 +1,108 @@
+use rand::prelude::*;
+
+use crate::search::{self, Evaluator, Metric, SearchConfig, SearchReport, SearchSpace, TracePoint};
+use crate::stats::Histogram;
+
+// Round a continuous particle position to a valid integer vector of the space
+fn repair_position(space: &SearchSpace, position: &[f64]) -> Vec<usize> {
+    let mut vector: Vec<usize> = position.iter().map(|&x| x.round().max(0.0) as usize).collect();
+    space.repair(&mut vector);
+    vector
+}
+
+pub fn particle_swarm(
+    rng: &mut StdRng,
+    histogram: &Histogram,
+    space: &SearchSpace,
+    initial: Option<Vec<usize>>,
+    metric: &Metric,
+    config: &SearchConfig,
+) -> (Vec<usize>, f64, SearchReport) {
+    let mut evaluator = Evaluator::new(histogram, metric, config);
+    let mut trace = vec![];
+    let (lower, upper) = (space.lower as f64, space.upper as f64);
+    let max_velocity = (upper - lower) * 0.2;
+
+    // Random positions and velocities, the first particle starts at the initial point when there is one
+    let mut positions: Vec<Vec<f64>> = (0..config.swarm_size)
+        .map(|_| space.random(rng).iter().map(|&x| x as f64).collect())
+        .collect();
+    if let Some(initial) = initial {
+        positions[0] = initial.iter().map(|&x| x as f64).collect();
+    }
+    let mut velocities: Vec<Vec<f64>> = (0..config.swarm_size)
+        .map(|_| {
+            (0..space.dimensions)
+                .map(|_| rng.gen_range(-max_velocity..=max_velocity))
+                .collect()
+        })
+        .collect();
+
+    // Personal and global bests, on the repaired positions
+    let mut personal_best: Vec<Vec<usize>> = positions.iter().map(|p| repair_position(space, p)).collect();
+    let mut personal_best_value: Vec<f64> = personal_best
+        .iter()
+        .map(|thresholds| evaluator.evaluate(thresholds))
+        .collect();
+    let mut best_particle = 0;
+    for i in 1..config.swarm_size {
+        if metric.direction.improves(personal_best_value[i], personal_best_value[best_particle]) {
+            best_particle = i;
+        }
+    }
+    let mut best_thresholds = personal_best[best_particle].clone();
+    let mut best_value = personal_best_value[best_particle];
+
+    let pb = search::progress_bar(config.pso_iterations as u64);
+
+    for iteration in 0..config.pso_iterations {
+        if evaluator.exhausted() {
+            break;
+        }
+        pb.inc(1);
+        let mut swarm_total = 0.0;
+        let mut moved = 0;
+        for i in 0..config.swarm_size {
+            if evaluator.exhausted() {
+                break;
+            }
+            moved += 1;
+            for d in 0..space.dimensions {
+                let r1: f64 = rng.gen();
+                let r2: f64 = rng.gen();
+                let velocity = config.inertia * velocities[i][d]
+                    + config.cognitive * r1 * (personal_best[i][d] as f64 - positions[i][d])
+                    + config.social * r2 * (best_thresholds[d] as f64 - positions[i][d]);
+                velocities[i][d] = velocity.clamp(-max_velocity, max_velocity);
+                positions[i][d] = (positions[i][d] + velocities[i][d]).clamp(lower, upper);
+            }
+
+            // Evaluate the particle on its repaired integer position
+            let thresholds = repair_position(space, &positions[i]);
+            let value = evaluator.evaluate(&thresholds);
+            swarm_total += value;
+            if metric.direction.improves(value, personal_best_value[i]) {
+                personal_best_value[i] = value;
+                personal_best[i] = thresholds.clone();
+                if metric.direction.improves(value, best_value) {
+                    best_value = value;
+                    best_thresholds = thresholds;
+                }
+            }
+        }
+        if moved == 0 {
+            break;
+        }
+
+        trace.push(TracePoint {
+            iteration: iteration + 1,
+            evaluations: evaluator.evaluations,
+            current: swarm_total / moved as f64,
+            best: best_value,
+            parameter: None,
+        });
+    }
+    pb.finish_with_message(format!("Done after {} iterations", config.pso_iterations));
+
+    (best_thresholds, best_value, evaluator.report(trace))
+}
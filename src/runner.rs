@@ -0,0 +1,300 @@
+use std::time::{Duration, Instant};
+
+use rand::prelude::*;
+
+use crate::differential_evolution::{self, Variant};
+use crate::search::{self, AnnealingSchedule, Metric, SearchConfig, SearchReport, SearchSpace};
+use crate::stats::Histogram;
+use crate::{file_writing, fuzzy, genetic, harmony_search, kapur, kittler, li, optimum_cache, otsu, pso, spatial};
+
+// Searchers that run directly on the k - 1 thresholds of a metric
+#[derive(Clone, Copy, PartialEq)]
+pub enum Method {
+    Exhaustive,
+    Sa,
+    Vns,
+    Tabu,
+    Hybrid,
+    Pso,
+    Ga,
+    DeRand,
+    DeBest,
+    Hs,
+}
+
+impl Method {
+    pub const ALL: [Method; 10] = [
+        Method::Exhaustive,
+        Method::Sa,
+        Method::Vns,
+        Method::Tabu,
+        Method::Hybrid,
+        Method::Pso,
+        Method::Ga,
+        Method::DeRand,
+        Method::DeBest,
+        Method::Hs,
+    ];
+
+    // The searchers that move a single threshold vector
+    pub const SINGLE_SOLUTION: [Method; 5] = [Method::Exhaustive, Method::Sa, Method::Vns, Method::Tabu, Method::Hybrid];
+
+    // Name on the command line and in the results folders
+    pub fn name(self) -> &'static str {
+        match self {
+            Method::Exhaustive => "exhaustive",
+            Method::Sa => "sa",
+            Method::Vns => "vns",
+            Method::Tabu => "tabu",
+            Method::Hybrid => "hybrid",
+            Method::Pso => "pso",
+            Method::Ga => "ga",
+            Method::DeRand => "de_rand",
+            Method::DeBest => "de_best",
+            Method::Hs => "hs",
+        }
+    }
+
+    fn label(self) -> &'static str {
+        match self {
+            Method::Exhaustive => "exhaustive search",
+            Method::Sa => "SA",
+            Method::Vns => "VNS",
+            Method::Tabu => "tabu search",
+            Method::Hybrid => "hybrid search",
+            Method::Pso => "PSO",
+            Method::Ga => "GA",
+            Method::DeRand => "DE rand/1/bin",
+            Method::DeBest => "DE best/1/bin",
+            Method::Hs => "HS",
+        }
+    }
+}
+
+// A metric evaluated directly on thresholds, with what its searchers need to know about it
+pub struct ThresholdMetric {
+    pub name: &'static str,
+    pub label: &'static str,
+    pub metric: &'static Metric,
+    // Shared by SA and the SA seed of the hybrid method
+    pub schedule: &'static AnnealingSchedule,
+    // SA starts at random thresholds rather than evenly spaced ones
+    pub random_annealing_start: bool,
+    pub methods: &'static [Method],
+}
+
+// How a metric/method pair named on the command line is run
+#[derive(Clone, Copy)]
+pub enum Runner {
+    // Metrics evaluated directly on thresholds, through compute_thresholds
+    Thresholds(&'static ThresholdMetric, Method),
+    // Metrics searched over other parameters, with their own entry points
+    Custom(fn(&str, &Histogram, usize, &SearchConfig) -> Vec<u8>),
+}
+
+impl Runner {
+    pub fn run(self, image_name: &str, histogram: &Histogram, k: usize, config: &SearchConfig) -> Vec<u8> {
+        match self {
+            Runner::Thresholds(metric, method) => compute_thresholds(metric, method, image_name, histogram, k, config),
+            Runner::Custom(compute_thresholds_fn) => compute_thresholds_fn(image_name, histogram, k, config),
+        }
+    }
+}
+
+pub const THRESHOLD_METRICS: [&ThresholdMetric; 4] = [
+    &otsu::OTSU_THRESHOLDS,
+    &kapur::KAPUR_THRESHOLDS,
+    &li::LI_THRESHOLDS,
+    &kittler::KITTLER_THRESHOLDS,
+];
+
+// Every metric/method pair that can be looked up by name from the command line
+pub fn available_methods() -> Vec<(&'static str, &'static str, Runner)> {
+    let mut methods = vec![];
+    for metric in THRESHOLD_METRICS {
+        for &method in metric.methods {
+            methods.push((metric.name, method.name(), Runner::Thresholds(metric, method)));
+        }
+    }
+    methods.extend([
+        ("fuzzy_trapezoid", "sa", Runner::Custom(fuzzy::compute_trapezoid_fuzzy_thresholds_simulated_annealing)),
+        ("fuzzy_trapezoid", "vns", Runner::Custom(fuzzy::compute_trapezoid_fuzzy_thresholds_variable_neighborhood)),
+        ("fuzzy_s", "sa", Runner::Custom(fuzzy::compute_s_fuzzy_thresholds_simulated_annealing)),
+        ("fuzzy_s", "vns", Runner::Custom(fuzzy::compute_s_fuzzy_thresholds_variable_neighborhood)),
+        ("fuzzy_type2", "sa", Runner::Custom(fuzzy::compute_type2_fuzzy_thresholds_simulated_annealing)),
+        ("fuzzy_type2", "vns", Runner::Custom(fuzzy::compute_type2_fuzzy_thresholds_variable_neighborhood)),
+        ("otsu_2d", "sa", Runner::Custom(spatial::compute_otsu_2d_thresholds_simulated_annealing)),
+        ("otsu_2d", "vns", Runner::Custom(spatial::compute_otsu_2d_thresholds_variable_neighborhood)),
+        ("kapur_2d", "sa", Runner::Custom(spatial::compute_kapur_2d_thresholds_simulated_annealing)),
+        ("kapur_2d", "vns", Runner::Custom(spatial::compute_kapur_2d_thresholds_variable_neighborhood)),
+    ]);
+    methods
+}
+
+pub fn find_method(metric_name: &str, method_name: &str) -> Runner {
+    available_methods()
+        .into_iter()
+        .find(|(metric, method, _)| *metric == metric_name && *method == method_name)
+        .map(|(_, _, runner)| runner)
+        .unwrap_or_else(|| panic!("Unknown metric/method pair {}/{}", metric_name, method_name))
+}
+
+pub fn compute_thresholds(
+    metric: &ThresholdMetric,
+    method: Method,
+    image_name: &str,
+    histogram: &Histogram,
+    k: usize,
+    config: &SearchConfig,
+) -> Vec<u8> {
+    let start_time = Instant::now();
+    if k < 2 {
+        panic!("The number of classes 'k' must be at least 2.");
+    }
+    if method == Method::Exhaustive {
+        return compute_exhaustive_thresholds(metric, image_name, histogram, k, start_time);
+    }
+
+    // A configured initialisation is the starting point of the single-solution searchers and
+    // one member of the population of the others
+    let mut rng = StdRng::seed_from_u64(config.seed);
+    let space = SearchSpace::thresholds(k);
+    let initial = search::starting_point(config, image_name, histogram, &space, &mut rng);
+    let (best_thresholds, best_value, report) = match method {
+        Method::Exhaustive => unreachable!(),
+        Method::Sa => {
+            let thresholds = match initial {
+                Some(thresholds) => thresholds,
+                None if metric.random_annealing_start => {
+                    let mut thresholds: Vec<usize> = (0..k - 1).map(|_| rng.gen_range(1..255)).collect();
+                    thresholds.sort();
+                    thresholds
+                }
+                None => space.evenly_spaced(),
+            };
+            search::simulated_annealing(&mut rng, histogram, &space, thresholds, metric.metric, metric.schedule, config)
+        }
+        Method::Vns => {
+            let thresholds = initial.unwrap_or_else(|| space.evenly_spaced());
+            search::variable_neighborhood(&mut rng, histogram, &space, thresholds, metric.metric, config)
+        }
+        Method::Tabu => {
+            let thresholds = initial.unwrap_or_else(|| space.evenly_spaced());
+            search::tabu_search(&mut rng, histogram, &space, thresholds, metric.metric, config)
+        }
+        Method::Hybrid => {
+            let thresholds = initial.unwrap_or_else(|| space.evenly_spaced());
+            let (best_thresholds, best_value, seed_value, report) =
+                search::hybrid(&mut rng, histogram, &space, thresholds, metric.metric, metric.schedule, config);
+            file_writing::write_refinement(
+                &format!("{}_hybrid_refinement.csv", metric.name),
+                image_name,
+                k,
+                seed_value,
+                best_value,
+                &best_thresholds,
+            );
+            println!("Refinement changed the objective by {} (seed {})", best_value - seed_value, seed_value);
+            (best_thresholds, best_value, report)
+        }
+        Method::Pso => pso::particle_swarm(&mut rng, histogram, &space, initial, metric.metric, config),
+        Method::Ga => genetic::genetic_algorithm(&mut rng, histogram, &space, initial, metric.metric, config),
+        Method::DeRand => differential_evolution::differential_evolution(
+            &mut rng,
+            histogram,
+            &space,
+            initial,
+            metric.metric,
+            Variant::Rand1Bin,
+            config,
+        ),
+        Method::DeBest => differential_evolution::differential_evolution(
+            &mut rng,
+            histogram,
+            &space,
+            initial,
+            metric.metric,
+            Variant::Best1Bin,
+            config,
+        ),
+        Method::Hs => harmony_search::harmony_search(&mut rng, histogram, &space, initial, metric.metric, config),
+    };
+
+    let run = RunLog {
+        metric_name: metric.name,
+        metric: metric.metric,
+        method_name: method.name(),
+        image_name,
+        k,
+    };
+    record_run(&run, histogram, &best_thresholds, best_value, &report, start_time.elapsed(), config);
+    println!("Optimal thresholds ({} with {}): {:?}", metric.label, method.label(), best_thresholds);
+
+    // Convert thresholds to u8
+    best_thresholds.iter().map(|&t| t as u8).collect()
+}
+
+// Which search a result comes from, for the logs written after it
+pub struct RunLog<'a> {
+    pub metric_name: &'a str,
+    pub metric: &'a Metric,
+    pub method_name: &'a str,
+    pub image_name: &'a str,
+    pub k: usize,
+}
+
+// Logs the timing and objective row, the optimality gap and, when asked for, the convergence
+// trace of a metaheuristic run
+pub fn record_run(
+    run: &RunLog,
+    histogram: &Histogram,
+    thresholds: &[usize],
+    value: f64,
+    report: &SearchReport,
+    duration: Duration,
+    config: &SearchConfig,
+) {
+    file_writing::writeln(
+        &format!("{}_{}_times.csv", run.metric_name, run.method_name),
+        run.image_name,
+        run.k,
+        thresholds,
+        duration,
+        value,
+        report,
+    );
+    optimum_cache::record_gap(run.metric_name, run.metric, run.method_name, run.image_name, histogram, run.k, value);
+    if config.trace {
+        file_writing::write_trace(
+            &search::trace_path(run.metric_name, run.method_name, run.image_name, run.k, config.seed),
+            &report.trace,
+        );
+    }
+}
+
+fn compute_exhaustive_thresholds(
+    metric: &ThresholdMetric,
+    image_name: &str,
+    histogram: &Histogram,
+    k: usize,
+    start_time: Instant,
+) -> Vec<u8> {
+    // Try all possible combinations of thresholds
+    let (best_thresholds, best_value, report) = search::exhaustive(histogram, k, metric.metric);
+
+    let duration = start_time.elapsed();
+    file_writing::writeln(
+        &format!("{}_exhaustive_times.csv", metric.name),
+        image_name,
+        k,
+        &best_thresholds,
+        duration,
+        best_value,
+        &report,
+    );
+    optimum_cache::record_optimum(metric.name, histogram, k, &best_thresholds, best_value);
+    println!("Optimal thresholds ({}): {:?}", metric.label, best_thresholds);
+
+    // Convert thresholds to u8
+    best_thresholds.iter().map(|&t| t as u8).collect()
+}
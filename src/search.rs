@@ -0,0 +1,1239 @@
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use clap::{Args, ValueEnum};
+use indicatif::{ProgressBar, ProgressStyle};
+use rand::prelude::*;
+use std::time::{Duration, Instant};
+
+use crate::file_writing;
+use crate::stats::{self, Histogram};
+
+// Whether a metric is better when it grows (Otsu, Kapur) or when it shrinks (Li)
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Direction {
+    Maximise,
+    Minimise,
+}
+
+impl Direction {
+    // True if `candidate` is strictly better than `incumbent`
+    pub fn improves(self, candidate: f64, incumbent: f64) -> bool {
+        match self {
+            Direction::Maximise => candidate > incumbent,
+            Direction::Minimise => candidate < incumbent,
+        }
+    }
+
+    // Signed change going from `current` to `candidate`, positive when it is an improvement
+    pub fn gain(self, candidate: f64, current: f64) -> f64 {
+        match self {
+            Direction::Maximise => candidate - current,
+            Direction::Minimise => current - candidate,
+        }
+    }
+
+    // Value every real objective value improves on
+    pub fn worst(self) -> f64 {
+        match self {
+            Direction::Maximise => f64::MIN,
+            Direction::Minimise => f64::MAX,
+        }
+    }
+}
+
+// A thresholding criterion: objective(histogram, thresholds, intensity_levels) and the way it is optimised
+pub struct Metric {
+    pub objective: fn(&Histogram, &[usize], usize) -> f64,
+    pub direction: Direction,
+}
+
+// Settings shared by the searchers, given on the command line
+#[derive(Args, Clone)]
+pub struct SearchConfig {
+    /// Random seed of the metaheuristics
+    #[arg(long, global = true, default_value_t = 42)]
+    pub seed: u64,
+    /// PSO: number of particles
+    #[arg(
+        long,
+        global = true,
+        value_parser = clap::builder::RangedU64ValueParser::<usize>::new().range(1..),
+        default_value_t = 30
+    )]
+    pub swarm_size: usize,
+    /// PSO: number of iterations
+    #[arg(long, global = true, default_value_t = 200)]
+    pub pso_iterations: usize,
+    /// PSO: inertia weight
+    #[arg(long, global = true, default_value_t = 0.72)]
+    pub inertia: f64,
+    /// PSO: cognitive coefficient, pull towards the particle's own best
+    #[arg(long, global = true, default_value_t = 1.49)]
+    pub cognitive: f64,
+    /// PSO: social coefficient, pull towards the swarm's best
+    #[arg(long, global = true, default_value_t = 1.49)]
+    pub social: f64,
+    /// GA, DE and NSGA-II: population size (DE uses at least 4)
+    #[arg(
+        long,
+        global = true,
+        value_parser = clap::builder::RangedU64ValueParser::<usize>::new().range(1..),
+        default_value_t = 100
+    )]
+    pub population: usize,
+    /// GA, DE and NSGA-II: number of generations
+    #[arg(long, global = true, default_value_t = 200)]
+    pub generations: usize,
+    /// GA: number of individuals drawn per tournament
+    #[arg(long, global = true, default_value_t = 3)]
+    pub tournament_size: usize,
+    /// GA: probability that two parents are crossed over
+    #[arg(long, global = true, value_parser = parse_rate, default_value_t = 0.9)]
+    pub crossover_rate: f64,
+    /// GA: probability that a child is mutated
+    #[arg(long, global = true, value_parser = parse_rate, default_value_t = 0.2)]
+    pub mutation_rate: f64,
+    /// GA: number of best individuals copied unchanged to the next generation
+    #[arg(long, global = true, default_value_t = 2)]
+    pub elitism: usize,
+    /// DE: differential weight F
+    #[arg(long, global = true, default_value_t = 0.5)]
+    pub de_weight: f64,
+    /// DE: binomial crossover rate CR
+    #[arg(long, global = true, value_parser = parse_rate, default_value_t = 0.9)]
+    pub de_crossover: f64,
+    /// HS: harmony memory size
+    #[arg(
+        long,
+        global = true,
+        value_parser = clap::builder::RangedU64ValueParser::<usize>::new().range(1..),
+        default_value_t = 30
+    )]
+    pub harmony_memory: usize,
+    /// HS: harmony memory considering rate
+    #[arg(long, global = true, value_parser = parse_rate, default_value_t = 0.9)]
+    pub harmony_rate: f64,
+    /// HS: pitch adjusting rate
+    #[arg(long, global = true, value_parser = parse_rate, default_value_t = 0.3)]
+    pub pitch_rate: f64,
+    /// HS: largest pitch adjustment, in grey levels
+    #[arg(long, global = true, default_value_t = 3)]
+    pub bandwidth: usize,
+    /// HS: number of improvisations
+    #[arg(long, global = true, default_value_t = 5000)]
+    pub hs_iterations: usize,
+    /// Tabu search: number of iterations a reversed move stays forbidden
+    #[arg(long, global = true, default_value_t = 7)]
+    pub tabu_tenure: usize,
+    /// Tabu search: maximum number of iterations
+    #[arg(long, global = true, default_value_t = 2000)]
+    pub tabu_iterations: usize,
+    /// Write the convergence trace of every metaheuristic run to CSV
+    #[arg(long, global = true)]
+    pub trace: bool,
+    /// Stop every metaheuristic after this many objective evaluations
+    #[arg(long, global = true)]
+    pub max_evaluations: Option<usize>,
+    /// Stop every metaheuristic after this many seconds
+    #[arg(long, global = true)]
+    pub time_limit: Option<f64>,
+    /// VNS: variant of the algorithm
+    #[arg(long, global = true, value_enum, default_value_t = VnsMode::Basic)]
+    pub vns_mode: VnsMode,
+    /// VNS: how the local search picks its move
+    #[arg(long, global = true, value_enum, default_value_t = LocalSearch::First)]
+    pub local_search: LocalSearch,
+    /// VNS: shaking neighbourhoods in order, as step:d, two:d or block:n
+    #[arg(
+        long,
+        global = true,
+        value_delimiter = ',',
+        default_values_t = [Neighbourhood::Step(1), Neighbourhood::Step(3), Neighbourhood::MoveTwo(1), Neighbourhood::Block(1)]
+    )]
+    pub neighbourhoods: Vec<Neighbourhood>,
+    /// Hybrid: metaheuristic that finds the region refined exactly
+    #[arg(long, global = true, value_enum, default_value_t = HybridSeed::Vns)]
+    pub hybrid_seed: HybridSeed,
+    /// Hybrid: every threshold is searched exhaustively within ±window of the seed
+    #[arg(long, global = true, default_value_t = 5)]
+    pub window: usize,
+    /// Starting point of every metaheuristic: default (each method's own), even, random, quantile,
+    /// or file:<run log csv> for the latest result on the same image and k
+    #[arg(long, global = true, default_value_t = Init::Default)]
+    pub init: Init,
+    /// Initial thresholds of every metaheuristic, comma separated, taking precedence over --init
+    #[arg(long, global = true, value_delimiter = ',')]
+    pub initial_thresholds: Option<Vec<usize>>,
+}
+
+// A probability given on the command line, in [0, 1]
+fn parse_rate(s: &str) -> Result<f64, String> {
+    let rate: f64 = s.parse().map_err(|_| format!("invalid rate '{}'", s))?;
+    if !(0.0..=1.0).contains(&rate) {
+        return Err(format!("rate {} is not between 0 and 1", rate));
+    }
+    Ok(rate)
+}
+
+// Where metaheuristics start. Population methods put the starting point in place of one
+// random member
+#[derive(Clone, Debug, PartialEq)]
+pub enum Init {
+    // Each method's own initialisation
+    Default,
+    // Thresholds evenly spaced over the levels
+    Even,
+    // Thresholds drawn at random
+    Random,
+    // Thresholds at the i/k quantiles of the histogram, every class holds as many pixels
+    Quantile,
+    // Thresholds of the latest run on the same image and k in a run log (*_times.csv)
+    File(PathBuf),
+}
+
+impl fmt::Display for Init {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Init::Default => write!(f, "default"),
+            Init::Even => write!(f, "even"),
+            Init::Random => write!(f, "random"),
+            Init::Quantile => write!(f, "quantile"),
+            Init::File(path) => write!(f, "file:{}", path.display()),
+        }
+    }
+}
+
+impl FromStr for Init {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "default" => Ok(Init::Default),
+            "even" => Ok(Init::Even),
+            "random" => Ok(Init::Random),
+            "quantile" => Ok(Init::Quantile),
+            _ => match s.split_once(':') {
+                Some(("file", path)) if !path.is_empty() => Ok(Init::File(PathBuf::from(path))),
+                _ => Err(format!(
+                    "unknown initialisation '{}', expected default, even, random, quantile or file:<path>",
+                    s
+                )),
+            },
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, ValueEnum)]
+pub enum HybridSeed {
+    /// Simulated annealing
+    Sa,
+    /// Variable neighbourhood search
+    Vns,
+}
+
+#[derive(Clone, Copy, Debug, ValueEnum)]
+pub enum VnsMode {
+    /// Shaking followed by a local search in the ±1 neighbourhood
+    Basic,
+    /// Shaking only, no local search
+    Reduced,
+    /// Shaking followed by a variable neighbourhood descent over the neighbourhoods
+    General,
+}
+
+#[derive(Clone, Copy, Debug, ValueEnum)]
+pub enum LocalSearch {
+    /// Take the first improving neighbour
+    First,
+    /// Scan the whole neighbourhood and take the best neighbour
+    Best,
+}
+
+// Neighbourhood structure used by the shaking and, when enumerable, by the descent
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Neighbourhood {
+    // One threshold moved by ±d
+    Step(usize),
+    // Two different thresholds each moved by ±d
+    MoveTwo(usize),
+    // A block of n consecutive thresholds redrawn at random between its neighbours
+    Block(usize),
+}
+
+impl fmt::Display for Neighbourhood {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Neighbourhood::Step(d) => write!(f, "step:{}", d),
+            Neighbourhood::MoveTwo(d) => write!(f, "two:{}", d),
+            Neighbourhood::Block(n) => write!(f, "block:{}", n),
+        }
+    }
+}
+
+impl FromStr for Neighbourhood {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (kind, size) = s
+            .split_once(':')
+            .ok_or_else(|| format!("expected step:d, two:d or block:n, got '{}'", s))?;
+        let size: usize = size
+            .parse()
+            .map_err(|_| format!("invalid size '{}' in neighbourhood '{}'", size, s))?;
+        if size == 0 {
+            return Err(format!("neighbourhood '{}' must have a size of at least 1", s));
+        }
+        match kind {
+            "step" => Ok(Neighbourhood::Step(size)),
+            "two" => Ok(Neighbourhood::MoveTwo(size)),
+            "block" => Ok(Neighbourhood::Block(size)),
+            _ => Err(format!("unknown neighbourhood '{}', expected step, two or block", kind)),
+        }
+    }
+}
+
+// One step of a search: the value it currently holds, the best value so far and the
+// control parameter of the method at that step (temperature, neighbourhood index, ...)
+pub struct TracePoint {
+    pub iteration: usize,
+    pub evaluations: usize,
+    pub current: f64,
+    pub best: f64,
+    pub parameter: Option<f64>,
+}
+
+// Smallest levels below which i/k of the pixels lie, for i = 1..k
+fn quantile_thresholds(histogram: &Histogram, k: usize) -> Vec<usize> {
+    (1..k)
+        .map(|i| {
+            let target = histogram.total * i as f64 / k as f64;
+            (1..=histogram.levels())
+                .find(|&level| histogram.cumulative_counts[level] >= target)
+                .unwrap_or(histogram.levels())
+        })
+        .collect()
+}
+
+// The k - 1 thresholds the configured initialisation starts from, unrepaired. None when each
+// method should use its own initialisation, including when a run log holds no matching run and
+// when the given thresholds do not fit k, as happens for all but one k of a multi-k command
+pub fn initial_thresholds(config: &SearchConfig, image_name: &str, histogram: &Histogram, k: usize, rng: &mut StdRng) -> Option<Vec<usize>> {
+    if let Some(thresholds) = &config.initial_thresholds {
+        if thresholds.len() != k - 1 {
+            println!(
+                "{} initial thresholds were given but k={} needs {}, using the default initialisation",
+                thresholds.len(),
+                k,
+                k - 1
+            );
+            return None;
+        }
+        return Some(thresholds.clone());
+    }
+    match &config.init {
+        Init::Default => None,
+        Init::Even => Some((1..k).map(|i| i * 255 / k).collect()),
+        Init::Random => {
+            let mut thresholds: Vec<usize> = (0..k - 1).map(|_| rng.gen_range(1..255)).collect();
+            thresholds.sort();
+            Some(thresholds)
+        }
+        Init::Quantile => Some(quantile_thresholds(histogram, k)),
+        Init::File(path) => {
+            let thresholds = file_writing::read_result_thresholds(Path::new(path), image_name, k);
+            if thresholds.is_none() {
+                println!("No run on {} with k={} in {}, using the default initialisation", image_name, k, path.display());
+            }
+            thresholds
+        }
+    }
+}
+
+// Starting point in a threshold space, the thresholds repeated for every group and repaired
+pub fn starting_point(
+    config: &SearchConfig,
+    image_name: &str,
+    histogram: &Histogram,
+    space: &SearchSpace,
+    rng: &mut StdRng,
+) -> Option<Vec<usize>> {
+    let thresholds = initial_thresholds(config, image_name, histogram, space.group_len() + 1, rng)?;
+    let mut vector = thresholds.repeat(space.groups);
+    space.repair(&mut vector);
+    Some(vector)
+}
+
+// Where the trace of one run is written, one file per seed so repeated runs do not overwrite it
+pub fn trace_path(metric_name: &str, method: &str, image_name: &str, k: usize, seed: u64) -> String {
+    format!("results/{}/{}/k{}/{}_k{}_seed{}_trace.csv", metric_name, method, k, image_name, k, seed)
+}
+
+// What a search did besides finding its thresholds
+pub struct SearchReport {
+    pub trace: Vec<TracePoint>,
+    pub evaluations: usize,
+    pub evaluations_to_best: usize,
+}
+
+// Objective of a metric on one histogram. It counts the evaluations, remembers after
+// how many the best value was found, and tells searchers when their budget is spent
+pub struct Evaluator<'a> {
+    histogram: &'a Histogram,
+    metric: &'a Metric,
+    pub evaluations: usize,
+    evaluations_to_best: usize,
+    best_value: f64,
+    max_evaluations: Option<usize>,
+    deadline: Option<Instant>,
+}
+
+impl<'a> Evaluator<'a> {
+    // Evaluator bounded by the evaluation and time budgets of the configuration
+    pub fn new(histogram: &'a Histogram, metric: &'a Metric, config: &SearchConfig) -> Self {
+        let mut evaluator = Evaluator::unbounded(histogram, metric);
+        evaluator.max_evaluations = config.max_evaluations;
+        evaluator.deadline = config
+            .time_limit
+            .map(|seconds| Instant::now() + Duration::from_secs_f64(seconds));
+        evaluator
+    }
+
+    pub fn unbounded(histogram: &'a Histogram, metric: &'a Metric) -> Self {
+        Evaluator {
+            histogram,
+            metric,
+            evaluations: 0,
+            evaluations_to_best: 0,
+            best_value: metric.direction.worst(),
+            max_evaluations: None,
+            deadline: None,
+        }
+    }
+
+    pub fn evaluate(&mut self, thresholds: &[usize]) -> f64 {
+        self.evaluations += 1;
+        let value = (self.metric.objective)(self.histogram, thresholds, self.histogram.levels());
+        if self.metric.direction.improves(value, self.best_value) {
+            self.best_value = value;
+            self.evaluations_to_best = self.evaluations;
+        }
+        value
+    }
+
+    // True once the evaluation or time budget is used up
+    pub fn exhausted(&self) -> bool {
+        self.max_evaluations.is_some_and(|max| self.evaluations >= max)
+            || self.deadline.is_some_and(|deadline| Instant::now() >= deadline)
+    }
+
+    pub fn report(&self, trace: Vec<TracePoint>) -> SearchReport {
+        SearchReport {
+            trace,
+            evaluations: self.evaluations,
+            evaluations_to_best: self.evaluations_to_best,
+        }
+    }
+}
+
+// Cooling schedule and stopping rules for simulated annealing
+pub struct AnnealingSchedule {
+    pub initial_temperature: f64,
+    pub cooling_rate: f64,
+    pub min_temperature: f64,
+    pub max_iterations: u64,
+    pub max_no_improvement: usize,
+}
+
+// Bounded integer vector the searchers work on. Thresholds are the ordered
+// case with k - 1 dimensions in 1..=254, other metrics may search membership
+// parameters, several threshold vectors side by side or unordered vectors
+pub struct SearchSpace {
+    pub dimensions: usize,
+    pub lower: usize,
+    pub upper: usize,
+    // Whether the vector has to stay strictly increasing
+    pub ordered: bool,
+    // Number of equal consecutive parts the vector is made of, ordering applies within each part
+    pub groups: usize,
+}
+
+impl SearchSpace {
+    pub fn thresholds(k: usize) -> SearchSpace {
+        SearchSpace {
+            dimensions: k - 1,
+            lower: 1,
+            upper: 254,
+            ordered: true,
+            groups: 1,
+        }
+    }
+
+    // Two threshold vectors, one per axis of a 2D histogram
+    pub fn threshold_pairs(k: usize) -> SearchSpace {
+        SearchSpace {
+            dimensions: 2 * (k - 1),
+            groups: 2,
+            ..SearchSpace::thresholds(k)
+        }
+    }
+
+    pub fn group_len(&self) -> usize {
+        self.dimensions / self.groups
+    }
+
+    // Whether dimensions i and j are ordered against each other
+    fn same_group(&self, i: usize, j: usize) -> bool {
+        i / self.group_len() == j / self.group_len()
+    }
+
+    pub fn random(&self, rng: &mut StdRng) -> Vec<usize> {
+        let mut vector: Vec<usize> = (0..self.dimensions)
+            .map(|_| rng.gen_range(self.lower..=self.upper))
+            .collect();
+        self.repair(&mut vector);
+        vector
+    }
+
+    pub fn evenly_spaced(&self) -> Vec<usize> {
+        let width = self.upper - self.lower + 2;
+        let len = self.group_len();
+        (0..self.dimensions)
+            .map(|i| self.lower - 1 + (i % len + 1) * width / (len + 1))
+            .collect()
+    }
+
+    // Clamp every value into range and, for ordered spaces, sort and spread
+    // out duplicates so every group is strictly increasing
+    pub fn repair(&self, vector: &mut [usize]) {
+        for value in vector.iter_mut() {
+            *value = (*value).clamp(self.lower, self.upper);
+        }
+        if !self.ordered || vector.is_empty() {
+            return;
+        }
+        for group in vector.chunks_mut(self.group_len()) {
+            self.repair_group(group);
+        }
+    }
+
+    fn repair_group(&self, vector: &mut [usize]) {
+        vector.sort_unstable();
+        for i in 1..vector.len() {
+            if vector[i] <= vector[i - 1] {
+                vector[i] = vector[i - 1] + 1;
+            }
+        }
+        let last = vector.len() - 1;
+        if vector[last] > self.upper {
+            vector[last] = self.upper;
+            for i in (0..last).rev() {
+                if vector[i] >= vector[i + 1] {
+                    vector[i] = vector[i + 1] - 1;
+                }
+            }
+        }
+    }
+
+    // Value of dimension `i` after moving it by `delta`, kept in range and,
+    // for ordered spaces, strictly between its neighbours
+    fn step(&self, vector: &[usize], i: usize, delta: isize) -> usize {
+        let (lower, upper) = (self.lower as isize, self.upper as isize);
+        let mut new_value = (vector[i] as isize + delta).clamp(lower, upper);
+        if self.ordered {
+            if i > 0 && self.same_group(i - 1, i) && new_value <= vector[i - 1] as isize {
+                new_value = (vector[i - 1] as isize + 1).clamp(lower, upper);
+            }
+            if i + 1 < vector.len() && self.same_group(i, i + 1) && new_value >= vector[i + 1] as isize {
+                new_value = (vector[i + 1] as isize - 1).clamp(lower, upper);
+            }
+        }
+        new_value as usize
+    }
+
+    // Whether dimension `i` can take `value` without leaving the space
+    fn admits(&self, vector: &[usize], i: usize, value: isize) -> bool {
+        if value < self.lower as isize || value > self.upper as isize {
+            return false;
+        }
+        if self.ordered {
+            if i > 0 && self.same_group(i - 1, i) && value <= vector[i - 1] as isize {
+                return false;
+            }
+            if i + 1 < vector.len() && self.same_group(i, i + 1) && value >= vector[i + 1] as isize {
+                return false;
+            }
+        }
+        true
+    }
+}
+
+pub fn progress_bar(len: u64) -> ProgressBar {
+    let pb = ProgressBar::new(len);
+    pb.set_style(
+        ProgressStyle::with_template(
+            "[{elapsed_precise}] {bar:40.white/gray} {pos:>7}/{len:7} {msg} [{duration_precise}]",
+        )
+        .unwrap(),
+    );
+    pb
+}
+
+pub fn exhaustive(histogram: &Histogram, k: usize, metric: &Metric) -> (Vec<usize>, f64, SearchReport) {
+    let intensity_levels = histogram.levels();
+    let mut evaluator = Evaluator::unbounded(histogram, metric);
+    let thresholds_combinations = stats::combinations(1, intensity_levels - 1, k - 1);
+
+    let pb = progress_bar(stats::combination_count(1, intensity_levels - 1, k - 1));
+
+    let mut best_value = metric.direction.worst();
+    let mut best_thresholds = vec![];
+
+    for thresholds in thresholds_combinations {
+        pb.inc(1);
+        let value = evaluator.evaluate(&thresholds);
+        if metric.direction.improves(value, best_value) {
+            best_value = value;
+            best_thresholds = thresholds;
+        }
+    }
+    pb.finish_with_message("Done");
+
+    (best_thresholds, best_value, evaluator.report(vec![]))
+}
+
+// Exact search of every ordered vector within ±window of the seed, never worse than the seed.
+// It goes on counting on the seed stage's evaluator and stops early when its budget runs out
+pub fn refine_window(
+    evaluator: &mut Evaluator,
+    space: &SearchSpace,
+    seed_thresholds: &[usize],
+    seed_value: f64,
+    metric: &Metric,
+    window: usize,
+) -> (Vec<usize>, f64) {
+    let lower: Vec<usize> = seed_thresholds
+        .iter()
+        .map(|&t| t.saturating_sub(window).max(space.lower))
+        .collect();
+    let upper: Vec<usize> = seed_thresholds.iter().map(|&t| (t + window).min(space.upper)).collect();
+    let thresholds_combinations = stats::bounded_combinations(&lower, &upper);
+
+    // Product of the window sizes, exact unless neighbouring windows overlap
+    let pb = progress_bar(lower.iter().zip(&upper).map(|(&l, &u)| (u + 1 - l) as u64).product());
+
+    let mut best_value = seed_value;
+    let mut best_thresholds = seed_thresholds.to_vec();
+
+    for thresholds in thresholds_combinations {
+        if evaluator.exhausted() {
+            break;
+        }
+        pb.inc(1);
+        let value = evaluator.evaluate(&thresholds);
+        if metric.direction.improves(value, best_value) {
+            best_value = value;
+            best_thresholds = thresholds;
+        }
+    }
+    pb.finish_with_message("Done");
+
+    (best_thresholds, best_value)
+}
+
+// SA or VNS seed refined by `refine_window`. Returns the refined thresholds and value, the
+// value of the seed, and a report whose evaluations and budget cover both stages
+pub fn hybrid(
+    rng: &mut StdRng,
+    histogram: &Histogram,
+    space: &SearchSpace,
+    initial_thresholds: Vec<usize>,
+    metric: &Metric,
+    schedule: &AnnealingSchedule,
+    config: &SearchConfig,
+) -> (Vec<usize>, f64, f64, SearchReport) {
+    let mut evaluator = Evaluator::new(histogram, metric, config);
+    let (seed_thresholds, seed_value, trace) = match config.hybrid_seed {
+        HybridSeed::Sa => run_simulated_annealing(rng, &mut evaluator, space, initial_thresholds, metric, schedule),
+        HybridSeed::Vns => run_variable_neighborhood(rng, &mut evaluator, space, initial_thresholds, metric, config),
+    };
+    let (best_thresholds, best_value) =
+        refine_window(&mut evaluator, space, &seed_thresholds, seed_value, metric, config.window);
+
+    (best_thresholds, best_value, seed_value, evaluator.report(trace))
+}
+
+pub fn simulated_annealing(
+    rng: &mut StdRng,
+    histogram: &Histogram,
+    space: &SearchSpace,
+    initial_thresholds: Vec<usize>,
+    metric: &Metric,
+    schedule: &AnnealingSchedule,
+    config: &SearchConfig,
+) -> (Vec<usize>, f64, SearchReport) {
+    let mut evaluator = Evaluator::new(histogram, metric, config);
+    let (best_thresholds, best_value, trace) =
+        run_simulated_annealing(rng, &mut evaluator, space, initial_thresholds, metric, schedule);
+    (best_thresholds, best_value, evaluator.report(trace))
+}
+
+// Simulated annealing on the given evaluator, so the hybrid method can spend one budget over
+// both of its stages. Returns the trace along with the best thresholds and value
+fn run_simulated_annealing(
+    rng: &mut StdRng,
+    evaluator: &mut Evaluator,
+    space: &SearchSpace,
+    initial_thresholds: Vec<usize>,
+    metric: &Metric,
+    schedule: &AnnealingSchedule,
+) -> (Vec<usize>, f64, Vec<TracePoint>) {
+    let mut trace = vec![];
+
+    let mut current_thresholds = initial_thresholds;
+    let mut current_value = evaluator.evaluate(&current_thresholds);
+    let mut best_thresholds = current_thresholds.clone();
+    let mut best_value = current_value;
+
+    let mut temperature = schedule.initial_temperature;
+
+    let pb = progress_bar(schedule.max_iterations);
+
+    let mut no_improvement_count = 0;
+    let mut iter = 0;
+    for _ in 0..schedule.max_iterations {
+        if temperature < schedule.min_temperature
+            || no_improvement_count >= schedule.max_no_improvement
+            || evaluator.exhausted()
+        {
+            break;
+        }
+        iter += 1;
+        pb.inc(1);
+
+        // Generate neighbor by moving one threshold by ±1
+        let mut neighbor_thresholds = current_thresholds.clone();
+        let i = rng.gen_range(0..space.dimensions);
+        let delta = if rng.gen_bool(0.5) { 1 } else { -1 };
+        neighbor_thresholds[i] = space.step(&neighbor_thresholds, i, delta);
+
+        let neighbor_value = evaluator.evaluate(&neighbor_thresholds);
+
+        // Positive gain is an improvement whichever way the metric is optimised
+        let gain = metric.direction.gain(neighbor_value, current_value);
+
+        // Acceptance probability
+        if gain >= 0.0 || rng.gen::<f64>() < f64::exp(gain / temperature) {
+            current_thresholds = neighbor_thresholds.clone();
+            current_value = neighbor_value;
+
+            if metric.direction.improves(neighbor_value, best_value) {
+                best_value = neighbor_value;
+                best_thresholds = neighbor_thresholds;
+                no_improvement_count = 0; // Reset no improvement counter
+            } else {
+                no_improvement_count += 1;
+            }
+        } else {
+            no_improvement_count += 1;
+        }
+
+        trace.push(TracePoint {
+            iteration: iter,
+            evaluations: evaluator.evaluations,
+            current: current_value,
+            best: best_value,
+            parameter: Some(temperature),
+        });
+
+        // Update temperature
+        temperature *= schedule.cooling_rate;
+    }
+    pb.finish_with_message(format!("Done after {} iterations", iter));
+
+    (best_thresholds, best_value, trace)
+}
+
+pub fn variable_neighborhood(
+    rng: &mut StdRng,
+    histogram: &Histogram,
+    space: &SearchSpace,
+    initial_thresholds: Vec<usize>,
+    metric: &Metric,
+    config: &SearchConfig,
+) -> (Vec<usize>, f64, SearchReport) {
+    let mut evaluator = Evaluator::new(histogram, metric, config);
+    let (best_thresholds, best_value, trace) =
+        run_variable_neighborhood(rng, &mut evaluator, space, initial_thresholds, metric, config);
+    (best_thresholds, best_value, evaluator.report(trace))
+}
+
+// VNS on the given evaluator, see `run_simulated_annealing`
+fn run_variable_neighborhood(
+    rng: &mut StdRng,
+    evaluator: &mut Evaluator,
+    space: &SearchSpace,
+    initial_thresholds: Vec<usize>,
+    metric: &Metric,
+    config: &SearchConfig,
+) -> (Vec<usize>, f64, Vec<TracePoint>) {
+    let mut trace = vec![];
+
+    let mut best_value = evaluator.evaluate(&initial_thresholds);
+    let mut best_thresholds = initial_thresholds.clone();
+
+    // VNS parameters
+    let neighbourhoods = &config.neighbourhoods;
+    let k_max = neighbourhoods.len();
+    let num_iterations = 100_000;
+    let mut current_thresholds = initial_thresholds;
+    let mut current_value = best_value;
+    let mut k_neigh = 1;
+
+    let pb = progress_bar(num_iterations);
+
+    let mut iter = 0;
+
+    let mut no_improvement_count = 0;
+    let max_no_improvement = 500; // Set a limit for iterations without improvement
+
+    while iter < num_iterations && no_improvement_count < max_no_improvement && !evaluator.exhausted() {
+        pb.inc(1);
+        iter += 1;
+
+        // Shaking
+        let neighbor_thresholds = shaking(rng, space, &current_thresholds, neighbourhoods[k_neigh - 1]);
+
+        let neighbor_value = evaluator.evaluate(&neighbor_thresholds);
+
+        // Local Search, skipped by reduced VNS
+        let (local_best_thresholds, local_best_value) = match config.vns_mode {
+            VnsMode::Basic => local_search(
+                evaluator,
+                space,
+                (neighbor_thresholds, neighbor_value),
+                metric,
+                Neighbourhood::Step(1),
+                config.local_search,
+            ),
+            VnsMode::Reduced => (neighbor_thresholds, neighbor_value),
+            VnsMode::General => variable_neighborhood_descent(
+                evaluator,
+                space,
+                (neighbor_thresholds, neighbor_value),
+                metric,
+                neighbourhoods,
+                config.local_search,
+            ),
+        };
+        let neighbourhood = k_neigh;
+
+        // Move or Not
+        if metric.direction.improves(local_best_value, current_value) {
+            current_thresholds = local_best_thresholds;
+            current_value = local_best_value;
+            if metric.direction.improves(current_value, best_value) {
+                best_value = current_value;
+                best_thresholds = current_thresholds.clone();
+                no_improvement_count = 0; // Reset no improvement counter
+            } else {
+                no_improvement_count += 1;
+            }
+            k_neigh = 1;
+        } else {
+            no_improvement_count += 1;
+            k_neigh += 1;
+            if k_neigh > k_max {
+                k_neigh = 1;
+            }
+        }
+
+        trace.push(TracePoint {
+            iteration: iter as usize,
+            evaluations: evaluator.evaluations,
+            current: current_value,
+            best: best_value,
+            parameter: Some(neighbourhood as f64),
+        });
+    }
+    pb.finish_with_message(format!("Done after {} iterations", iter));
+
+    (best_thresholds, best_value, trace)
+}
+
+// Iteration until which each (threshold index, direction) move is tabu, 0 is down and 1 is up
+struct TabuList {
+    until: Vec<[usize; 2]>,
+}
+
+impl TabuList {
+    fn new(dimensions: usize) -> Self {
+        TabuList {
+            until: vec![[0; 2]; dimensions],
+        }
+    }
+
+    // Forbids undoing a move made at iteration `iter` for the next `tenure` iterations
+    fn forbid_reverse(&mut self, moved: usize, direction: usize, iter: usize, tenure: usize) {
+        self.until[moved][1 - direction] = iter + tenure;
+    }
+
+    // A tabu move is only allowed when it beats the best value found so far (aspiration)
+    fn admits(&self, moved: usize, direction: usize, iter: usize, value: f64, best_value: f64, metric: &Metric) -> bool {
+        self.until[moved][direction] < iter || metric.direction.improves(value, best_value)
+    }
+}
+
+// Tabu search over the shaking moves. Moving threshold i up makes moving it back down
+// tabu for `tabu_tenure` iterations (and the other way round), unless the move would
+// beat the best solution found so far
+pub fn tabu_search(
+    rng: &mut StdRng,
+    histogram: &Histogram,
+    space: &SearchSpace,
+    initial_thresholds: Vec<usize>,
+    metric: &Metric,
+    config: &SearchConfig,
+) -> (Vec<usize>, f64, SearchReport) {
+    let mut evaluator = Evaluator::new(histogram, metric, config);
+    let mut trace = vec![];
+
+    let mut current_thresholds = initial_thresholds;
+    let mut current_value = evaluator.evaluate(&current_thresholds);
+    let mut best_thresholds = current_thresholds.clone();
+    let mut best_value = current_value;
+
+    let mut tabu_list = TabuList::new(space.dimensions);
+
+    let pb = progress_bar(config.tabu_iterations as u64);
+
+    let mut iter = 0;
+    let mut no_improvement_count = 0;
+    let max_no_improvement = 500;
+
+    while iter < config.tabu_iterations && no_improvement_count < max_no_improvement && !evaluator.exhausted() {
+        pb.inc(1);
+        iter += 1;
+
+        // Neighbourhood: every threshold moved by ±1 and ±3, plus one random replacement
+        let mut moves = vec![];
+        for i in 0..space.dimensions {
+            for delta in [-3, -1, 1, 3] {
+                let mut neighbor_thresholds = current_thresholds.clone();
+                neighbor_thresholds[i] = space.step(&neighbor_thresholds, i, delta);
+                moves.push((i, neighbor_thresholds));
+            }
+        }
+        let replaced = rng.gen_range(0..space.dimensions);
+        moves.push((replaced, shaking(rng, space, &current_thresholds, Neighbourhood::Block(1))));
+
+        // Best admissible neighbour, tabu moves only pass the aspiration criterion
+        let mut chosen: Option<(usize, usize, Vec<usize>, f64)> = None;
+        for (i, neighbor_thresholds) in moves {
+            if evaluator.exhausted() {
+                break;
+            }
+            if neighbor_thresholds == current_thresholds {
+                continue;
+            }
+            // Repair may shift other thresholds, the direction is taken from the one that moved most
+            let moved = (0..space.dimensions)
+                .max_by_key(|&j| (neighbor_thresholds[j] as isize - current_thresholds[j] as isize).abs())
+                .unwrap_or(i);
+            let direction = (neighbor_thresholds[moved] > current_thresholds[moved]) as usize;
+            let neighbor_value = evaluator.evaluate(&neighbor_thresholds);
+
+            if !tabu_list.admits(moved, direction, iter, neighbor_value, best_value, metric) {
+                continue;
+            }
+            if chosen
+                .as_ref()
+                .is_none_or(|(_, _, _, value)| metric.direction.improves(neighbor_value, *value))
+            {
+                chosen = Some((moved, direction, neighbor_thresholds, neighbor_value));
+            }
+        }
+
+        // Every move is tabu and none aspires, wait for the list to clear
+        let Some((moved, direction, neighbor_thresholds, neighbor_value)) = chosen else {
+            no_improvement_count += 1;
+            continue;
+        };
+
+        tabu_list.forbid_reverse(moved, direction, iter, config.tabu_tenure);
+        current_thresholds = neighbor_thresholds;
+        current_value = neighbor_value;
+
+        if metric.direction.improves(current_value, best_value) {
+            best_value = current_value;
+            best_thresholds = current_thresholds.clone();
+            no_improvement_count = 0;
+        } else {
+            no_improvement_count += 1;
+        }
+
+        trace.push(TracePoint {
+            iteration: iter,
+            evaluations: evaluator.evaluations,
+            current: current_value,
+            best: best_value,
+            parameter: None,
+        });
+    }
+    pb.finish_with_message(format!("Done after {} iterations", iter));
+
+    (best_thresholds, best_value, evaluator.report(trace))
+}
+
+// Random point in the given neighbourhood of the current thresholds
+pub fn shaking(
+    rng: &mut StdRng,
+    space: &SearchSpace,
+    current_thresholds: &[usize],
+    neighbourhood: Neighbourhood,
+) -> Vec<usize> {
+    let mut neighbor_thresholds = current_thresholds.to_vec();
+    let dimensions = space.dimensions;
+    let random_delta = |rng: &mut StdRng, d: usize| if rng.gen_bool(0.5) { d as isize } else { -(d as isize) };
+
+    match neighbourhood {
+        Neighbourhood::Step(d) => {
+            // Modify one threshold by ±d
+            let i = rng.gen_range(0..dimensions);
+            let delta = random_delta(rng, d);
+            neighbor_thresholds[i] = space.step(&neighbor_thresholds, i, delta);
+        }
+        Neighbourhood::MoveTwo(d) => {
+            // Modify two different thresholds by ±d each
+            let i = rng.gen_range(0..dimensions);
+            let delta = random_delta(rng, d);
+            neighbor_thresholds[i] = space.step(&neighbor_thresholds, i, delta);
+            if dimensions >= 2 {
+                let j = (i + rng.gen_range(1..dimensions)) % dimensions;
+                let delta = random_delta(rng, d);
+                neighbor_thresholds[j] = space.step(&neighbor_thresholds, j, delta);
+            }
+        }
+        Neighbourhood::Block(n) => {
+            // Redraw a block of consecutive thresholds between the ones around it
+            let n = n.min(dimensions);
+            let start = rng.gen_range(0..=dimensions - n);
+            let (mut lower, mut upper) = (space.lower, space.upper);
+            if space.ordered {
+                if start > 0 && space.same_group(start - 1, start) {
+                    lower = (neighbor_thresholds[start - 1] + 1).min(space.upper);
+                }
+                if start + n < dimensions && space.same_group(start + n - 1, start + n) {
+                    upper = neighbor_thresholds[start + n].saturating_sub(1).max(lower);
+                }
+            }
+            for value in neighbor_thresholds[start..start + n].iter_mut() {
+                *value = rng.gen_range(lower..=upper);
+            }
+            space.repair(&mut neighbor_thresholds);
+        }
+    }
+
+    neighbor_thresholds
+}
+
+// Every point of an enumerable neighbourhood that stays inside the space.
+// Block neighbourhoods are random and have no enumeration
+fn neighbours(space: &SearchSpace, current_thresholds: &[usize], neighbourhood: Neighbourhood) -> Vec<Vec<usize>> {
+    let mut result = vec![];
+    let moved = |vector: &[usize], i: usize, delta: isize| {
+        let new_value = vector[i] as isize + delta;
+        if space.admits(vector, i, new_value) {
+            let mut neighbor = vector.to_vec();
+            neighbor[i] = new_value as usize;
+            Some(neighbor)
+        } else {
+            None
+        }
+    };
+    match neighbourhood {
+        Neighbourhood::Step(d) => {
+            for i in 0..space.dimensions {
+                for delta in [-(d as isize), d as isize] {
+                    result.extend(moved(current_thresholds, i, delta));
+                }
+            }
+        }
+        Neighbourhood::MoveTwo(d) => {
+            for i in 0..space.dimensions {
+                for j in i + 1..space.dimensions {
+                    for delta_i in [-(d as isize), d as isize] {
+                        for delta_j in [-(d as isize), d as isize] {
+                            // Move the threshold that frees room first so shifting both together stays valid
+                            let (first, second) = if delta_i > 0 {
+                                ((j, delta_j), (i, delta_i))
+                            } else {
+                                ((i, delta_i), (j, delta_j))
+                            };
+                            if let Some(neighbor) = moved(current_thresholds, first.0, first.1) {
+                                result.extend(moved(&neighbor, second.0, second.1));
+                            }
+                        }
+                    }
+                }
+            }
+        }
+        Neighbourhood::Block(_) => {}
+    }
+    result
+}
+
+// Hill climbing in one neighbourhood from an evaluated start, with first or best improvement
+fn local_search(
+    evaluator: &mut Evaluator,
+    space: &SearchSpace,
+    initial: (Vec<usize>, f64),
+    metric: &Metric,
+    neighbourhood: Neighbourhood,
+    strategy: LocalSearch,
+) -> (Vec<usize>, f64) {
+    let (mut current_thresholds, mut current_value) = initial;
+    let max_local_iterations = 100; // To prevent infinite loops
+
+    for _ in 0..max_local_iterations {
+        let mut improvement: Option<(Vec<usize>, f64)> = None;
+        for neighbor_thresholds in neighbours(space, &current_thresholds, neighbourhood) {
+            if evaluator.exhausted() {
+                break;
+            }
+            let neighbor_value = evaluator.evaluate(&neighbor_thresholds);
+            let incumbent = improvement.as_ref().map_or(current_value, |(_, value)| *value);
+            if metric.direction.improves(neighbor_value, incumbent) {
+                improvement = Some((neighbor_thresholds, neighbor_value));
+                if let LocalSearch::First = strategy {
+                    break;
+                }
+            }
+        }
+        match improvement {
+            Some((thresholds, value)) => {
+                current_thresholds = thresholds;
+                current_value = value;
+            }
+            None => break,
+        }
+        if evaluator.exhausted() {
+            break;
+        }
+    }
+    (current_thresholds, current_value)
+}
+
+// Local search in each enumerable neighbourhood in turn, going back to the first one after every improvement
+fn variable_neighborhood_descent(
+    evaluator: &mut Evaluator,
+    space: &SearchSpace,
+    initial: (Vec<usize>, f64),
+    metric: &Metric,
+    neighbourhoods: &[Neighbourhood],
+    strategy: LocalSearch,
+) -> (Vec<usize>, f64) {
+    let descents: Vec<Neighbourhood> = neighbourhoods
+        .iter()
+        .copied()
+        .filter(|neighbourhood| !matches!(neighbourhood, Neighbourhood::Block(_)))
+        .collect();
+    if descents.is_empty() {
+        return local_search(evaluator, space, initial, metric, Neighbourhood::Step(1), strategy);
+    }
+
+    let (mut current_thresholds, mut current_value) = initial;
+    let mut l = 0;
+    while l < descents.len() && !evaluator.exhausted() {
+        let (thresholds, value) = local_search(
+            evaluator,
+            space,
+            (current_thresholds.clone(), current_value),
+            metric,
+            descents[l],
+            strategy,
+        );
+        if metric.direction.improves(value, current_value) {
+            current_thresholds = thresholds;
+            current_value = value;
+            l = 0;
+        } else {
+            l += 1;
+        }
+    }
+    (current_thresholds, current_value)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn repair_clamps_sorts_and_spreads_duplicates() {
+        let space = SearchSpace::thresholds(4);
+        let mut vector = vec![200, 0, 200];
+        space.repair(&mut vector);
+        assert_eq!(vector, vec![1, 200, 201]);
+
+        // Duplicates at the upper bound are spread downwards
+        let mut vector = vec![300, 254, 254];
+        space.repair(&mut vector);
+        assert_eq!(vector, vec![252, 253, 254]);
+    }
+
+    #[test]
+    fn repair_of_unordered_spaces_only_clamps() {
+        let space = SearchSpace {
+            ordered: false,
+            ..SearchSpace::thresholds(4)
+        };
+        let mut vector = vec![0, 300, 5];
+        space.repair(&mut vector);
+        assert_eq!(vector, vec![1, 254, 5]);
+    }
+
+    #[test]
+    fn repair_orders_every_group_separately() {
+        let space = SearchSpace::threshold_pairs(3);
+        let mut vector = vec![90, 30, 300, 10];
+        space.repair(&mut vector);
+        assert_eq!(vector, vec![30, 90, 10, 254]);
+        assert_eq!(space.evenly_spaced(), vec![85, 170, 85, 170]);
+    }
+
+    #[test]
+    fn steps_stop_between_the_neighbours() {
+        let space = SearchSpace::thresholds(4);
+        let vector = [10, 20, 30];
+        assert_eq!(space.step(&vector, 1, 50), 29);
+        assert_eq!(space.step(&vector, 1, -50), 11);
+        assert_eq!(space.step(&vector, 0, -50), 1);
+        assert_eq!(space.step(&vector, 2, 300), 254);
+
+        // The first value of the second group is not held above the last of the first
+        let space = SearchSpace::threshold_pairs(3);
+        assert_eq!(space.step(&[100, 200, 150, 160], 2, -100), 50);
+    }
+
+    #[test]
+    fn tabu_moves_wait_for_the_tenure_unless_they_aspire() {
+        let metric = Metric {
+            objective: |_, _, _| 0.0,
+            direction: Direction::Maximise,
+        };
+        let mut tabu_list = TabuList::new(2);
+        // Threshold 0 moved up at iteration 5, moving it down is tabu for 3 iterations
+        tabu_list.forbid_reverse(0, 1, 5, 3);
+        for iter in 6..=8 {
+            assert!(!tabu_list.admits(0, 0, iter, 1.0, 2.0, &metric));
+        }
+        assert!(tabu_list.admits(0, 0, 9, 1.0, 2.0, &metric));
+        // The same direction and the other threshold stay free
+        assert!(tabu_list.admits(0, 1, 6, 1.0, 2.0, &metric));
+        assert!(tabu_list.admits(1, 0, 6, 1.0, 2.0, &metric));
+        // Aspiration: a tabu move beating the best value is taken anyway
+        assert!(tabu_list.admits(0, 0, 6, 3.0, 2.0, &metric));
+        assert!(!tabu_list.admits(0, 0, 6, 2.0, 2.0, &metric));
+    }
+}
@@ -0,0 +1,104 @@
+use std::cmp::Ordering;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::histogram_processing::{self, Smoothing};
+
+// Splits a name into runs of digits and runs of anything else
+fn chunks(name: &str) -> Vec<&str> {
+    let mut chunks = vec![];
+    let mut start = 0;
+    for (i, c) in name.char_indices().skip(1) {
+        let previous = name[..i].chars().next_back().unwrap();
+        if c.is_ascii_digit() != previous.is_ascii_digit() {
+            chunks.push(&name[start..i]);
+            start = i;
+        }
+    }
+    if start < name.len() {
+        chunks.push(&name[start..]);
+    }
+    chunks
+}
+
+// Natural order, runs of digits compare by their value so frame2 comes before frame10
+pub fn natural_cmp(a: &str, b: &str) -> Ordering {
+    for (x, y) in chunks(a).iter().zip(chunks(b).iter()) {
+        let ordering = if x.starts_with(|c: char| c.is_ascii_digit()) && y.starts_with(|c: char| c.is_ascii_digit()) {
+            let (x, y) = (x.trim_start_matches('0'), y.trim_start_matches('0'));
+            x.len().cmp(&y.len()).then_with(|| x.cmp(y))
+        } else {
+            x.cmp(y)
+        };
+        if ordering != Ordering::Equal {
+            return ordering;
+        }
+    }
+    chunks(a).len().cmp(&chunks(b).len()).then_with(|| a.cmp(b))
+}
+
+// Frame number of a file name matching the pattern, {} standing for the number
+fn frame_number(name: &str, pattern: &str) -> Option<u64> {
+    let (prefix, suffix) = pattern
+        .split_once("{}")
+        .unwrap_or_else(|| panic!("The frame pattern '{}' has no {{}} in place of the frame number", pattern));
+    let number = name.strip_prefix(prefix)?.strip_suffix(suffix)?;
+    if number.is_empty() || !number.chars().all(|c| c.is_ascii_digit()) {
+        return None;
+    }
+    number.parse().ok()
+}
+
+// Frames of the folder in order: the files matching the pattern by frame number, or every
+// image in natural order of the file names
+pub fn frame_paths(dir: &Path, pattern: Option<&str>) -> Vec<PathBuf> {
+    let mut frames: Vec<(PathBuf, String)> = fs::read_dir(dir)
+        .expect("Failed to read directory")
+        .map(|entry| entry.expect("Failed to read directory entry").path())
+        .filter(|path| path.is_file())
+        .map(|path| {
+            let name = path.file_name().unwrap().to_string_lossy().to_string();
+            (path, name)
+        })
+        .collect();
+
+    match pattern {
+        Some(pattern) => {
+            let mut numbered: Vec<(u64, PathBuf)> = frames
+                .into_iter()
+                .filter_map(|(path, name)| frame_number(&name, pattern).map(|number| (number, path)))
+                .collect();
+            numbered.sort_by_key(|(number, _)| *number);
+            numbered.into_iter().map(|(_, path)| path).collect()
+        }
+        None => {
+            frames.retain(|(path, _)| {
+                path.extension()
+                    .is_some_and(|ext| ext == "jpg" || ext == "png" || ext == "jpeg")
+            });
+            frames.sort_by(|a, b| natural_cmp(&a.1, &b.1));
+            frames.into_iter().map(|(path, _)| path).collect()
+        }
+    }
+}
+
+// Smooths every threshold over the frames with the histogram smoothing kernels. All thresholds
+// share the weights, so the smoothed thresholds of a frame stay in order
+pub fn smooth(thresholds: &[Vec<u8>], smoothing: Smoothing, sigma: f64, radius: usize) -> Vec<Vec<u8>> {
+    if smoothing == Smoothing::None || thresholds.is_empty() {
+        return thresholds.to_vec();
+    }
+    let series: Vec<Vec<f64>> = (0..thresholds[0].len())
+        .map(|i| {
+            let values: Vec<f64> = thresholds.iter().map(|frame| frame[i] as f64).collect();
+            match smoothing {
+                Smoothing::Gaussian => histogram_processing::gaussian_smoothing(&values, sigma),
+                Smoothing::MovingAverage => histogram_processing::moving_average(&values, radius),
+                Smoothing::None => unreachable!(),
+            }
+        })
+        .collect();
+    (0..thresholds.len())
+        .map(|frame| series.iter().map(|values| values[frame].round() as u8).collect())
+        .collect()
+}
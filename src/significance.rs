@@ -0,0 +1,230 @@
+// Non-parametric tests used to compare search methods over repeated seeded runs
+
+// Ranks starting at 1, ties share the mean of the ranks they span
+fn average_ranks(values: &[f64]) -> Vec<f64> {
+    let mut order: Vec<usize> = (0..values.len()).collect();
+    order.sort_by(|&a, &b| values[a].partial_cmp(&values[b]).unwrap_or(std::cmp::Ordering::Equal));
+
+    let mut ranks = vec![0.0; values.len()];
+    let mut i = 0;
+    while i < order.len() {
+        let mut j = i;
+        while j + 1 < order.len() && values[order[j + 1]] == values[order[i]] {
+            j += 1;
+        }
+        let rank = (i + j) as f64 / 2.0 + 1.0;
+        for &index in &order[i..=j] {
+            ranks[index] = rank;
+        }
+        i = j + 1;
+    }
+    ranks
+}
+
+// Sizes of the groups of tied values
+fn tie_sizes(values: &[f64]) -> Vec<usize> {
+    let mut sorted = values.to_vec();
+    sorted.sort_by(|a, b| a.partial_cmp(b).unwrap_or(std::cmp::Ordering::Equal));
+    let mut sizes = vec![];
+    let mut i = 0;
+    while i < sorted.len() {
+        let mut j = i;
+        while j + 1 < sorted.len() && sorted[j + 1] == sorted[i] {
+            j += 1;
+        }
+        sizes.push(j - i + 1);
+        i = j + 1;
+    }
+    sizes
+}
+
+// Abramowitz and Stegun 7.1.26, absolute error below 1.5e-7
+fn erf(x: f64) -> f64 {
+    let sign = if x < 0.0 { -1.0 } else { 1.0 };
+    let x = x.abs();
+    let t = 1.0 / (1.0 + 0.3275911 * x);
+    let polynomial = t * (0.254829592 + t * (-0.284496736 + t * (1.421413741 + t * (-1.453152027 + t * 1.061405429))));
+    sign * (1.0 - polynomial * (-x * x).exp())
+}
+
+fn normal_cdf(z: f64) -> f64 {
+    0.5 * (1.0 + erf(z / std::f64::consts::SQRT_2))
+}
+
+// Lanczos approximation of ln Gamma(x) for x > 0
+fn ln_gamma(x: f64) -> f64 {
+    let coefficients = [
+        76.18009172947146,
+        -86.50532032941677,
+        24.01409824083091,
+        -1.231739572450155,
+        0.1208650973866179e-2,
+        -0.5395239384953e-5,
+    ];
+    let tmp = x + 5.5;
+    let tmp = tmp - (x + 0.5) * tmp.ln();
+    let mut series = 1.000000000190015;
+    let mut y = x;
+    for c in coefficients {
+        y += 1.0;
+        series += c / y;
+    }
+    -tmp + (2.5066282746310005 * series / x).ln()
+}
+
+// Regularised upper incomplete gamma Q(a, x), by series below a + 1 and continued fraction above
+fn gamma_q(a: f64, x: f64) -> f64 {
+    if x <= 0.0 {
+        return 1.0;
+    }
+    let ln_prefactor = -x + a * x.ln() - ln_gamma(a);
+    if x < a + 1.0 {
+        let mut term = 1.0 / a;
+        let mut sum = term;
+        let mut n = a;
+        for _ in 0..1000 {
+            n += 1.0;
+            term *= x / n;
+            sum += term;
+            if term.abs() < sum.abs() * 1e-14 {
+                break;
+            }
+        }
+        1.0 - sum * ln_prefactor.exp()
+    } else {
+        let tiny = 1e-300;
+        let mut b = x + 1.0 - a;
+        let mut c = 1.0 / tiny;
+        let mut d = 1.0 / b;
+        let mut h = d;
+        for i in 1..1000 {
+            let an = -(i as f64) * (i as f64 - a);
+            b += 2.0;
+            d = an * d + b;
+            if d.abs() < tiny {
+                d = tiny;
+            }
+            c = b + an / c;
+            if c.abs() < tiny {
+                c = tiny;
+            }
+            d = 1.0 / d;
+            let delta = d * c;
+            h *= delta;
+            if (delta - 1.0).abs() < 1e-14 {
+                break;
+            }
+        }
+        ln_prefactor.exp() * h
+    }
+}
+
+// Wilcoxon signed-rank test on paired samples, returns the statistic min(W+, W-) and the
+// two-sided p-value from the normal approximation with tie and continuity corrections
+pub fn wilcoxon_signed_rank(a: &[f64], b: &[f64]) -> (f64, f64) {
+    let differences: Vec<f64> = a.iter().zip(b).map(|(x, y)| x - y).filter(|d| *d != 0.0).collect();
+    let n = differences.len();
+    if n == 0 {
+        return (0.0, 1.0);
+    }
+
+    let magnitudes: Vec<f64> = differences.iter().map(|d| d.abs()).collect();
+    let ranks = average_ranks(&magnitudes);
+    let positive = ranks
+        .iter()
+        .zip(&differences)
+        .filter(|(_, d)| **d > 0.0)
+        .fold(0.0, |sum, (r, _)| sum + r);
+    let total = (n * (n + 1)) as f64 / 2.0;
+    let statistic = positive.min(total - positive);
+
+    let n = n as f64;
+    let mean = n * (n + 1.0) / 4.0;
+    let tie_correction: f64 = tie_sizes(&magnitudes).iter().map(|&t| (t * t * t - t) as f64).sum::<f64>() / 48.0;
+    let variance = n * (n + 1.0) * (2.0 * n + 1.0) / 24.0 - tie_correction;
+    if variance <= 0.0 {
+        return (statistic, 1.0);
+    }
+    let z = ((positive - mean).abs() - 0.5).max(0.0) / variance.sqrt();
+    (statistic, (2.0 * (1.0 - normal_cdf(z))).min(1.0))
+}
+
+// Friedman test with one sample per treatment, all paired by block (the seed of the run).
+// Lower values get lower ranks, so callers negate maximised objectives. Returns the
+// tie-corrected chi-square statistic and its p-value with treatments - 1 degrees of freedom
+pub fn friedman(samples: &[Vec<f64>]) -> Option<(f64, f64)> {
+    let treatments = samples.len();
+    let blocks = samples.iter().map(|s| s.len()).min().unwrap_or(0);
+    if treatments < 2 || blocks < 2 {
+        return None;
+    }
+
+    let mut rank_sums = vec![0.0; treatments];
+    let mut tie_term = 0.0;
+    for block in 0..blocks {
+        let values: Vec<f64> = samples.iter().map(|s| s[block]).collect();
+        for (t, rank) in average_ranks(&values).iter().enumerate() {
+            rank_sums[t] += rank;
+        }
+        tie_term += tie_sizes(&values).iter().map(|&t| (t * t * t - t) as f64).sum::<f64>();
+    }
+
+    let (n, k) = (blocks as f64, treatments as f64);
+    let denominator = n * k * (k + 1.0) - tie_term / (k - 1.0);
+    if denominator <= 0.0 {
+        // Every block is a complete tie
+        return Some((0.0, 1.0));
+    }
+    let sum_squares: f64 = rank_sums.iter().map(|r| r * r).sum();
+    let statistic = (12.0 * sum_squares - 3.0 * n * n * k * (k + 1.0) * (k + 1.0)) / denominator;
+    let p_value = gamma_q((k - 1.0) / 2.0, statistic / 2.0);
+    Some((statistic, p_value))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn close(a: f64, b: f64, tolerance: f64) -> bool {
+        (a - b).abs() < tolerance
+    }
+
+    #[test]
+    fn ties_share_their_mean_rank() {
+        assert_eq!(average_ranks(&[3.0, 1.0, 3.0, 2.0]), vec![3.5, 1.0, 3.5, 2.0]);
+        assert_eq!(tie_sizes(&[3.0, 1.0, 3.0, 2.0]), vec![1, 1, 2]);
+    }
+
+    // Differences 1, -1, 2, 2, 3, -3, 4 and a dropped zero: |d| ranks 1.5, 1.5, 3.5, 3.5, 5.5,
+    // 5.5, 7, so W+ = 21 and W- = 7. The variance 7 * 8 * 15 / 24 = 35 loses 3 * (2^3 - 2) / 48
+    // for the three ties, z = (|21 - 14| - 0.5) / sqrt(34.625)
+    #[test]
+    fn wilcoxon_with_ties_and_zero_differences() {
+        let a = [1.0, 0.0, 2.0, 2.0, 3.0, 0.0, 4.0, 5.0];
+        let b = [0.0, 1.0, 0.0, 0.0, 0.0, 3.0, 0.0, 5.0];
+        let (statistic, p_value) = wilcoxon_signed_rank(&a, &b);
+        assert_eq!(statistic, 7.0);
+        assert!(close(p_value, 0.2693181470962549, 1e-6), "p = {}", p_value);
+    }
+
+    #[test]
+    fn wilcoxon_without_differences() {
+        assert_eq!(wilcoxon_signed_rank(&[1.0, 2.0], &[1.0, 2.0]), (0.0, 1.0));
+    }
+
+    // Rank sums 4.5, 9 and 10.5 over 4 blocks of 3 treatments give 12 / 48 * 211.5 - 48 = 4.875,
+    // the two ties divide it by 1 - 12 / 96. With 2 degrees of freedom p = exp(-chi2 / 2)
+    #[test]
+    fn friedman_with_tied_blocks() {
+        let samples = vec![vec![1.0, 2.0, 1.0, 1.0], vec![2.0, 2.0, 3.0, 2.0], vec![3.0, 3.0, 2.0, 2.0]];
+        let (statistic, p_value) = friedman(&samples).unwrap();
+        assert!(close(statistic, 39.0 / 7.0, 1e-12), "chi2 = {}", statistic);
+        assert!(close(p_value, (-39.0f64 / 14.0).exp(), 1e-9), "p = {}", p_value);
+    }
+
+    #[test]
+    fn friedman_of_complete_ties() {
+        assert_eq!(friedman(&[vec![1.0, 2.0], vec![1.0, 2.0]]), Some((0.0, 1.0)));
+        assert_eq!(friedman(&[vec![1.0, 2.0]]), None);
+    }
+}
@@ -0,0 +1,197 @@
+use std::fs;
+use std::time::Instant;
+
+use rand::{rngs::StdRng, SeedableRng};
+
+use crate::search::{self, AnnealingSchedule, Direction, Metric, SearchConfig, SearchSpace};
+use crate::runner::{self, RunLog};
+use crate::stats::{Histogram, JointHistogram};
+use crate::{histogram_drawer, kapur, otsu};
+
+// Otsu's between-class variance on the 2D histogram of intensity against neighbourhood mean,
+// summed over both axes, higher is better
+pub const OTSU_2D: Metric = Metric {
+    objective: calculate_2d_between_class_variance,
+    direction: Direction::Maximise,
+};
+
+// Kapur's entropy on the 2D histogram of intensity against neighbourhood mean, higher is better
+pub const KAPUR_2D: Metric = Metric {
+    objective: calculate_2d_total_entropy,
+    direction: Direction::Maximise,
+};
+
+// Metrics read from the joint histogram rather than the intensity histogram
+pub fn is_2d(metric_name: &str) -> bool {
+    metric_name.ends_with("_2d")
+}
+
+fn joint(histogram: &Histogram) -> &JointHistogram {
+    histogram
+        .joint
+        .as_ref()
+        .expect("2D metrics need the joint histogram of an image")
+}
+
+// The searched vector holds the k - 1 intensity thresholds followed by the k - 1 neighbourhood
+// mean thresholds. Class c is the block of pixels whose intensity and mean both fall in the
+// c-th interval, the off-diagonal blocks are mostly edges and noise and are left out
+fn class_blocks(pairs: &[usize]) -> Vec<(usize, usize, usize, usize)> {
+    let (intensity, mean) = pairs.split_at(pairs.len() / 2);
+    let bounds = |thresholds: &[usize]| {
+        let mut bounds = vec![0];
+        bounds.extend_from_slice(thresholds);
+        bounds.push(JointHistogram::LEVELS);
+        bounds
+    };
+    let (intensity, mean) = (bounds(intensity), bounds(mean));
+    (0..intensity.len() - 1)
+        .map(|c| (intensity[c], intensity[c + 1], mean[c], mean[c + 1]))
+        .collect()
+}
+
+pub fn calculate_2d_between_class_variance(histogram: &Histogram, pairs: &[usize], _intensity_levels: usize) -> f64 {
+    let joint = joint(histogram);
+    let levels = JointHistogram::LEVELS;
+    let (_, total_intensity, total_mean, _) = joint.rectangle(0, levels, 0, levels);
+
+    class_blocks(pairs)
+        .into_iter()
+        .map(|(i0, i1, j0, j1)| {
+            let (prob, intensity, mean, _) = joint.rectangle(i0, i1, j0, j1);
+            if prob > 0.0 {
+                prob * ((intensity / prob - total_intensity).powi(2) + (mean / prob - total_mean).powi(2))
+            } else {
+                0.0
+            }
+        })
+        .sum()
+}
+
+// The entropy of a block normalised by its probability P is ln P - sum(p ln p) / P
+pub fn calculate_2d_total_entropy(histogram: &Histogram, pairs: &[usize], _intensity_levels: usize) -> f64 {
+    let joint = joint(histogram);
+    class_blocks(pairs)
+        .into_iter()
+        .map(|(i0, i1, j0, j1)| {
+            let (prob, _, _, entropy) = joint.rectangle(i0, i1, j0, j1);
+            if prob > 0.0 {
+                prob.ln() - entropy / prob
+            } else {
+                0.0
+            }
+        })
+        .sum()
+}
+
+pub fn compute_otsu_2d_thresholds_simulated_annealing(image_name: &str, histogram: &Histogram, k: usize, config: &SearchConfig) -> Vec<u8> {
+    compute_2d_thresholds_simulated_annealing(image_name, histogram, k, &OTSU_2D, "otsu_2d", otsu::OTSU_THRESHOLDS.schedule, config)
+}
+
+pub fn compute_otsu_2d_thresholds_variable_neighborhood(image_name: &str, histogram: &Histogram, k: usize, config: &SearchConfig) -> Vec<u8> {
+    compute_2d_thresholds_variable_neighborhood(image_name, histogram, k, &OTSU_2D, "otsu_2d", config)
+}
+
+pub fn compute_kapur_2d_thresholds_simulated_annealing(image_name: &str, histogram: &Histogram, k: usize, config: &SearchConfig) -> Vec<u8> {
+    compute_2d_thresholds_simulated_annealing(image_name, histogram, k, &KAPUR_2D, "kapur_2d", kapur::KAPUR_THRESHOLDS.schedule, config)
+}
+
+pub fn compute_kapur_2d_thresholds_variable_neighborhood(image_name: &str, histogram: &Histogram, k: usize, config: &SearchConfig) -> Vec<u8> {
+    compute_2d_thresholds_variable_neighborhood(image_name, histogram, k, &KAPUR_2D, "kapur_2d", config)
+}
+
+fn compute_2d_thresholds_simulated_annealing(
+    image_name: &str,
+    histogram: &Histogram,
+    k: usize,
+    metric: &Metric,
+    metric_name: &str,
+    schedule: &AnnealingSchedule,
+    config: &SearchConfig,
+) -> Vec<u8> {
+    let start_time = Instant::now();
+    if k < 2 {
+        panic!("The number of classes 'k' must be at least 2.");
+    }
+
+    // Initialize threshold pairs at random, a configured initialisation is used for both axes
+    let space = SearchSpace::threshold_pairs(k);
+    let mut rng = StdRng::seed_from_u64(config.seed);
+    let pairs = search::starting_point(config, image_name, histogram, &space, &mut rng)
+        .unwrap_or_else(|| space.random(&mut rng));
+
+    let (best_pairs, max_value, report) =
+        search::simulated_annealing(&mut rng, histogram, &space, pairs, metric, schedule, config);
+
+    // The logs hold the intensity thresholds, like the 1D runs
+    let run = RunLog {
+        metric_name,
+        metric,
+        method_name: "sa",
+        image_name,
+        k,
+    };
+    runner::record_run(&run, histogram, &best_pairs[..k - 1], max_value, &report, start_time.elapsed(), config);
+
+    draw_and_convert(image_name, histogram, k, metric_name, "sa", &best_pairs)
+}
+
+fn compute_2d_thresholds_variable_neighborhood(
+    image_name: &str,
+    histogram: &Histogram,
+    k: usize,
+    metric: &Metric,
+    metric_name: &str,
+    config: &SearchConfig,
+) -> Vec<u8> {
+    let start_time = Instant::now();
+    if k < 2 {
+        panic!("The number of classes 'k' must be at least 2.");
+    }
+
+    // Initialize both threshold vectors to equally spaced values, unless an initialisation is configured
+    let mut rng = StdRng::seed_from_u64(config.seed);
+    let space = SearchSpace::threshold_pairs(k);
+    let pairs = search::starting_point(config, image_name, histogram, &space, &mut rng)
+        .unwrap_or_else(|| space.evenly_spaced());
+    let (best_pairs, max_value, report) =
+        search::variable_neighborhood(&mut rng, histogram, &space, pairs, metric, config);
+
+    // The logs hold the intensity thresholds, like the 1D runs
+    let run = RunLog {
+        metric_name,
+        metric,
+        method_name: "vns",
+        image_name,
+        k,
+    };
+    runner::record_run(&run, histogram, &best_pairs[..k - 1], max_value, &report, start_time.elapsed(), config);
+
+    draw_and_convert(image_name, histogram, k, metric_name, "vns", &best_pairs)
+}
+
+// Draws the 2D histogram with both threshold vectors, the image is segmented on the intensity thresholds
+fn draw_and_convert(image_name: &str, histogram: &Histogram, k: usize, metric_name: &str, method: &str, best_pairs: &[usize]) -> Vec<u8> {
+    let (intensity_thresholds, mean_thresholds) = best_pairs.split_at(k - 1);
+    // The run folders only go up to k5, auto-k and tiled runs can ask for more classes
+    let dir = format!("results/{}/{}/k{}/histogram", metric_name, method, k);
+    fs::create_dir_all(&dir).expect("Failed to create directory");
+    histogram_drawer::draw_joint_histogram(
+        image_name,
+        metric_name,
+        method,
+        joint(histogram),
+        &format!(
+            "{}/{}_k{}_joint_histogram_{:?}_{:?}.png",
+            dir, image_name, k, intensity_thresholds, mean_thresholds
+        ),
+        intensity_thresholds,
+        mean_thresholds,
+    );
+
+    println!("Optimal intensity thresholds ({} with {}): {:?}", metric_name, method.to_uppercase(), intensity_thresholds);
+    println!("Optimal neighbourhood mean thresholds ({} with {}): {:?}", metric_name, method.to_uppercase(), mean_thresholds);
+
+    // Convert thresholds to u8
+    intensity_thresholds.iter().map(|&t| t as u8).collect()
+}
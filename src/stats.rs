@@ -1,54 +1,353 @@
+use image::GrayImage;
+
+// Every strictly increasing vector of k values in start..=end, in lexicographic order. The
+// vectors are generated one at a time, there are billions of them for k around 5
+pub struct Combinations {
+    next: Option<Vec<usize>>,
+    end: usize,
+}
+
+impl Iterator for Combinations {
+    type Item = Vec<usize>;
+
+    fn next(&mut self) -> Option<Vec<usize>> {
+        let current = self.next.take()?;
+        // Move up the last value that still can, the values after it follow on directly
+        let k = current.len();
+        if let Some(i) = (0..k).rev().find(|&i| current[i] < self.end + 1 + i - k) {
+            let mut next = current.clone();
+            next[i] += 1;
+            for j in i + 1..k {
+                next[j] = next[j - 1] + 1;
+            }
+            self.next = Some(next);
+        }
+        Some(current)
+    }
+}
+
 // Function to generate all combinations of thresholds
-pub fn combinations(start: usize, end: usize, k: usize) -> Vec<Vec<usize>> {
-    fn combine(start: usize, end: usize, k: usize, prefix: &mut Vec<usize>, result: &mut Vec<Vec<usize>>) {
-        if k == 0 {
-            result.push(prefix.clone());
-            return;
+pub fn combinations(start: usize, end: usize, k: usize) -> Combinations {
+    Combinations {
+        next: (start + k <= end + 1).then(|| (start..start + k).collect()),
+        end,
+    }
+}
+
+// Number of vectors `combinations` generates, n choose k
+pub fn combination_count(start: usize, end: usize, k: usize) -> u64 {
+    let n = (end + 1).saturating_sub(start) as u64;
+    if k as u64 > n {
+        return 0;
+    }
+    (0..k as u64).fold(1, |count, i| count * (n - i) / (i + 1))
+}
+
+// Strictly increasing vectors whose i-th value lies in lower[i]..=upper[i], in lexicographic
+// order. The bounded counterpart of `Combinations` used to search a window around every threshold
+pub struct BoundedCombinations {
+    next: Option<Vec<usize>>,
+    lower: Vec<usize>,
+    upper: Vec<usize>,
+}
+
+impl BoundedCombinations {
+    // Sets values from index `from` on to the smallest ones the bounds and the ordering allow
+    fn fill(&self, vector: &mut Vec<usize>, from: usize) -> bool {
+        vector.truncate(from);
+        for i in from..self.lower.len() {
+            let value = match vector.last() {
+                Some(&previous) => self.lower[i].max(previous + 1),
+                None => self.lower[i],
+            };
+            if value > self.upper[i] {
+                return false;
+            }
+            vector.push(value);
         }
-        for i in start..=end - k + 1 {
-            prefix.push(i);
-            combine(i + 1, end, k - 1, prefix, result);
-            prefix.pop();
+        true
+    }
+}
+
+impl Iterator for BoundedCombinations {
+    type Item = Vec<usize>;
+
+    fn next(&mut self) -> Option<Vec<usize>> {
+        let current = self.next.take()?;
+        // Move up the last value that still can with the values after it kept as low as possible
+        for i in (0..current.len()).rev() {
+            if current[i] < self.upper[i] {
+                let mut next = current[..i].to_vec();
+                next.push(current[i] + 1);
+                if self.fill(&mut next, i + 1) {
+                    self.next = Some(next);
+                    break;
+                }
+            }
         }
+        Some(current)
     }
+}
 
-    let mut result = vec![];
-    combine(start, end, k, &mut vec![], &mut result);
-    result
+// Starts at the smallest vector within the bounds, there is none when the windows leave no room
+pub fn bounded_combinations(lower: &[usize], upper: &[usize]) -> BoundedCombinations {
+    let mut combinations = BoundedCombinations {
+        next: None,
+        lower: lower.to_vec(),
+        upper: upper.to_vec(),
+    };
+    let mut first = vec![];
+    if combinations.fill(&mut first, 0) {
+        combinations.next = Some(first);
+    }
+    combinations
 }
 
-// Function to calculate the between-class variance for given thresholds
-pub fn calculate_between_class_variance(prob: &[f64], thresholds: &[usize], intensity_levels: usize) -> f64 {
+// Grey-level histogram with its normalised form and prefix sums, computed once per image so
+// objectives can take class statistics in constant time. Counts are f64 so histograms read
+// from files or smoothed ones need not be integral
+pub struct Histogram {
+    pub counts: Vec<f64>,
+    pub prob: Vec<f64>,
+    // Entry i covers levels 0..i, so every vector is one longer than the histogram
+    pub cumulative_counts: Vec<f64>,
+    pub cumulative_moment: Vec<f64>,
+    pub cumulative_square: Vec<f64>,
+    pub total: f64,
+    // Intensity against neighbourhood mean, only known when the histogram comes from an image
+    pub joint: Option<JointHistogram>,
+}
+
+impl Histogram {
+    pub fn from_counts(counts: Vec<f64>) -> Self {
+        if counts.iter().any(|&c| c < 0.0 || !c.is_finite()) {
+            panic!("Histogram counts must be finite and non-negative");
+        }
+        let total: f64 = counts.iter().sum();
+        if total <= 0.0 {
+            panic!("Histogram is empty");
+        }
+
+        let prob = counts.iter().map(|&count| count / total).collect();
+        let mut cumulative_counts = vec![0.0];
+        let mut cumulative_moment = vec![0.0];
+        let mut cumulative_square = vec![0.0];
+        for (i, &count) in counts.iter().enumerate() {
+            let level = i as f64;
+            cumulative_counts.push(cumulative_counts[i] + count);
+            cumulative_moment.push(cumulative_moment[i] + level * count);
+            cumulative_square.push(cumulative_square[i] + level * level * count);
+        }
+
+        Histogram {
+            counts,
+            prob,
+            cumulative_counts,
+            cumulative_moment,
+            cumulative_square,
+            total,
+            joint: None,
+        }
+    }
+
+    pub fn from_image(gray_img: &GrayImage) -> Self {
+        let mut counts = vec![0.0; 256];
+        for pixel in gray_img.pixels() {
+            counts[pixel[0] as usize] += 1.0;
+        }
+        Histogram::from_counts(counts)
+    }
+
+    // Adds the joint histogram of intensity against neighbourhood mean, only the 2D metrics read it
+    pub fn with_joint(mut self, gray_img: &GrayImage) -> Self {
+        self.joint = Some(JointHistogram::from_image(gray_img));
+        self
+    }
+
+    pub fn levels(&self) -> usize {
+        self.counts.len()
+    }
+
+    // Weight, first and second moments of the levels start..end, in counts
+    fn range(&self, start: usize, end: usize) -> (f64, f64, f64) {
+        (
+            self.cumulative_counts[end] - self.cumulative_counts[start],
+            self.cumulative_moment[end] - self.cumulative_moment[start],
+            self.cumulative_square[end] - self.cumulative_square[start],
+        )
+    }
+
+    pub fn mean(&self) -> f64 {
+        self.cumulative_moment[self.levels()] / self.total
+    }
+
+    pub fn variance(&self) -> f64 {
+        let mean = self.mean();
+        (self.cumulative_square[self.levels()] / self.total - mean * mean).max(0.0)
+    }
+}
+
+// 2D histogram of (intensity, rounded mean of the 3 x 3 neighbourhood) pairs with summed-area
+// tables, so the probability, moments and entropy term of any rectangle of levels take
+// constant time
+#[derive(Clone)]
+pub struct JointHistogram {
+    pub prob: Vec<f64>,
+    // Entry (i, j) covers intensities 0..i and means 0..j, stored row-major with LEVELS + 1 columns
+    cumulative_prob: Vec<f64>,
+    cumulative_intensity: Vec<f64>,
+    cumulative_mean: Vec<f64>,
+    cumulative_entropy: Vec<f64>,
+}
+
+impl JointHistogram {
+    pub const LEVELS: usize = 256;
+
+    // The neighbourhood is clamped at the borders, so edge pixels average fewer neighbours
+    pub fn from_image(gray_img: &GrayImage) -> Self {
+        let (width, height) = gray_img.dimensions();
+        let mut counts = vec![0.0; Self::LEVELS * Self::LEVELS];
+        for (x, y, pixel) in gray_img.enumerate_pixels() {
+            let mut sum = 0u32;
+            let mut size = 0u32;
+            for ny in y.saturating_sub(1)..=(y + 1).min(height - 1) {
+                for nx in x.saturating_sub(1)..=(x + 1).min(width - 1) {
+                    sum += gray_img.get_pixel(nx, ny)[0] as u32;
+                    size += 1;
+                }
+            }
+            let mean = (sum as f64 / size as f64).round() as usize;
+            counts[pixel[0] as usize * Self::LEVELS + mean] += 1.0;
+        }
+
+        let total = (width * height) as f64;
+        let prob: Vec<f64> = counts.iter().map(|&count| count / total).collect();
+        let side = Self::LEVELS + 1;
+        let table = |value: &dyn Fn(usize, usize, f64) -> f64| {
+            let mut table = vec![0.0; side * side];
+            for i in 0..Self::LEVELS {
+                for j in 0..Self::LEVELS {
+                    table[(i + 1) * side + j + 1] = value(i, j, prob[i * Self::LEVELS + j])
+                        + table[i * side + j + 1]
+                        + table[(i + 1) * side + j]
+                        - table[i * side + j];
+                }
+            }
+            table
+        };
+        JointHistogram {
+            cumulative_prob: table(&|_, _, p| p),
+            cumulative_intensity: table(&|i, _, p| i as f64 * p),
+            cumulative_mean: table(&|_, j, p| j as f64 * p),
+            cumulative_entropy: table(&|_, _, p| if p > 0.0 { p * p.ln() } else { 0.0 }),
+            prob,
+        }
+    }
+
+    fn sum(table: &[f64], i0: usize, i1: usize, j0: usize, j1: usize) -> f64 {
+        let side = Self::LEVELS + 1;
+        table[i1 * side + j1] - table[i0 * side + j1] - table[i1 * side + j0] + table[i0 * side + j0]
+    }
+
+    // Probability, intensity moment, mean moment and sum of p ln p over intensities
+    // i0..i1 and neighbourhood means j0..j1
+    pub fn rectangle(&self, i0: usize, i1: usize, j0: usize, j1: usize) -> (f64, f64, f64, f64) {
+        (
+            Self::sum(&self.cumulative_prob, i0, i1, j0, j1),
+            Self::sum(&self.cumulative_intensity, i0, i1, j0, j1),
+            Self::sum(&self.cumulative_mean, i0, i1, j0, j1),
+            Self::sum(&self.cumulative_entropy, i0, i1, j0, j1),
+        )
+    }
+}
+
+// Probability, mean and variance of every class delimited by the thresholds
+pub struct ClassStatistics {
+    pub prob: Vec<f64>,
+    pub mean: Vec<f64>,
+    pub variance: Vec<f64>,
+}
+
+pub fn calculate_class_statistics(histogram: &Histogram, thresholds: &[usize], intensity_levels: usize) -> ClassStatistics {
     let mut class_prob = vec![];
     let mut class_mean = vec![];
+    let mut class_variance = vec![];
     let mut start = 0;
-    let mut total_mean = 0.0;
-
-    for (i, &p) in prob.iter().enumerate() {
-        total_mean += i as f64 * p;
-    }
 
     for &threshold in thresholds.iter().chain(std::iter::once(&intensity_levels)) {
-        let mut sum_prob = 0.0;
-        let mut sum_mean = 0.0;
-        for i in start..threshold {
-            sum_prob += prob[i];
-            sum_mean += i as f64 * prob[i];
-        }
-        if sum_prob > 0.0 {
-            class_prob.push(sum_prob);
-            class_mean.push(sum_mean / sum_prob);
+        let (count, moment, square) = histogram.range(start, threshold);
+        if count > 0.0 {
+            let mean = moment / count;
+            class_prob.push(count / histogram.total);
+            class_mean.push(mean);
+            class_variance.push((square / count - mean * mean).max(0.0));
         } else {
             class_prob.push(0.0);
             class_mean.push(0.0);
+            class_variance.push(0.0);
         }
         start = threshold;
     }
 
+    ClassStatistics {
+        prob: class_prob,
+        mean: class_mean,
+        variance: class_variance,
+    }
+}
+
+// Function to calculate the between-class variance for given thresholds
+pub fn calculate_between_class_variance(histogram: &Histogram, thresholds: &[usize], intensity_levels: usize) -> f64 {
+    let total_mean = histogram.mean();
+
+    let classes = calculate_class_statistics(histogram, thresholds, intensity_levels);
+
     let mut sigma_between = 0.0;
-    for (i, &p) in class_prob.iter().enumerate() {
-        sigma_between += p * (class_mean[i] - total_mean).powi(2);
+    for (i, &p) in classes.prob.iter().enumerate() {
+        sigma_between += p * (classes.mean[i] - total_mean).powi(2);
     }
 
     sigma_between
-}
\ No newline at end of file
+}
+
+// Variance of the grey levels over the whole histogram
+pub fn calculate_total_variance(histogram: &Histogram) -> f64 {
+    histogram.variance()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn combinations_in_lexicographic_order() {
+        let all: Vec<Vec<usize>> = combinations(1, 4, 2).collect();
+        assert_eq!(all, vec![vec![1, 2], vec![1, 3], vec![1, 4], vec![2, 3], vec![2, 4], vec![3, 4]]);
+        assert_eq!(combinations(1, 3, 3).collect::<Vec<_>>(), vec![vec![1, 2, 3]]);
+        assert_eq!(combinations(1, 3, 4).count(), 0);
+    }
+
+    #[test]
+    fn combination_count_matches_the_iterator() {
+        for k in 1..=4 {
+            assert_eq!(combination_count(1, 30, k), combinations(1, 30, k).count() as u64);
+        }
+        assert_eq!(combination_count(1, 254, 3), 2_699_004);
+        assert_eq!(combination_count(1, 3, 4), 0);
+    }
+
+    #[test]
+    fn bounded_combinations_stay_increasing_in_overlapping_windows() {
+        assert_eq!(
+            bounded_combinations(&[1, 2], &[2, 3]).collect::<Vec<_>>(),
+            vec![vec![1, 2], vec![1, 3], vec![2, 3]]
+        );
+        assert_eq!(bounded_combinations(&[3, 3], &[4, 4]).collect::<Vec<_>>(), vec![vec![3, 4]]);
+        assert_eq!(bounded_combinations(&[5, 5], &[5, 5]).count(), 0);
+        let (lower, upper) = ([2, 3, 4], [6, 6, 7]);
+        let within: Vec<Vec<usize>> = combinations(2, 7, 3)
+            .filter(|v| (0..3).all(|i| (lower[i]..=upper[i]).contains(&v[i])))
+            .collect();
+        assert_eq!(bounded_combinations(&lower, &upper).collect::<Vec<_>>(), within);
+    }
+}